@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+
+/// Write `target` crash-safely. `write` fills a temporary file created in the target's directory,
+/// which is flushed to disk, checked by `validate`, given the permissions and ownership of
+/// `original` (the target itself when it exists) and then renamed over the target in one step.
+/// On any error the temporary file is removed and the target is left untouched.
+///
+/// A symbolic link is followed, so the file it points to is replaced and the link is kept.
+/// A target with other hard links is refused: renaming over it would leave the original
+/// content under the other names.
+pub fn write_file(
+    target: &Path,
+    original: &Path,
+    write: impl FnOnce(&Path) -> Result<()>,
+    validate: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    let target = &target.canonicalize().unwrap_or_else(|_| target.to_path_buf());
+    check_links(target)?;
+    let dir = match target.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    let temp = tempfile::Builder::new()
+        .prefix(".medars-")
+        .suffix(".tmp")
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?;
+    write(temp.path())?;
+    File::open(temp.path())
+        .and_then(|f| f.sync_all())
+        .context("Failed to flush the cleaned image to disk")?;
+    validate(temp.path()).context("The cleaned image is invalid; the original was left untouched")?;
+    let source = if target.exists() { target } else { original };
+    copy_permissions(source, temp.path())?;
+    temp.persist(target)
+        .with_context(|| format!("Failed to replace {}", target.display()))?;
+    sync_dir(dir);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn check_links(target: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    match std::fs::metadata(target) {
+        Ok(metadata) if metadata.nlink() > 1 => bail!(
+            "{} has {} hard links, which would keep the original; use --copy to clean a copy instead",
+            target.display(),
+            metadata.nlink()
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_links(_target: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Give `path` the mode, owner and group of `source`. Changing the owner needs privileges,
+/// so a refused change of owner is not an error.
+#[cfg(unix)]
+fn copy_permissions(source: &Path, path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(source)?;
+    std::fs::set_permissions(path, metadata.permissions())?;
+    if std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = std::os::unix::fs::chown(path, None, Some(metadata.gid()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_permissions(source: &Path, path: &Path) -> Result<()> {
+    std::fs::set_permissions(path, std::fs::metadata(source)?.permissions())?;
+    Ok(())
+}
+
+/// Flush the directory entry of the rename, where the platform supports it
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(content: &'static [u8]) -> impl FnOnce(&Path) -> Result<()> {
+        move |path| Ok(fs::write(path, content)?)
+    }
+
+    /// Names in a directory, sorted
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> =
+            fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn failed_validation_leaves_the_original_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("photo.jpg");
+        fs::write(&target, b"original").unwrap();
+        let result = write_file(&target, &target, write(b"cleaned"), |_| bail!("broken"));
+        assert!(result.is_err());
+        assert_eq!(fs::read(&target).unwrap(), b"original");
+        assert_eq!(names(dir.path()), ["photo.jpg"]);
+
+        write_file(&target, &target, write(b"cleaned"), |_| Ok(())).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"cleaned");
+        assert_eq!(names(dir.path()), ["photo.jpg"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_are_followed_or_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("photo.jpg");
+        let link = dir.path().join("link.jpg");
+        fs::write(&target, b"original").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_file(&link, &link, write(b"cleaned"), |_| Ok(())).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), b"cleaned");
+
+        let hard_link = dir.path().join("hard.jpg");
+        fs::hard_link(&target, &hard_link).unwrap();
+        assert!(write_file(&target, &target, write(b"again"), |_| Ok(())).is_err());
+        assert_eq!(fs::read(&hard_link).unwrap(), b"cleaned");
+        assert_eq!(names(dir.path()), ["hard.jpg", "link.jpg", "photo.jpg"]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::ffi::OsString;
+use std::path::Path;
+use anyhow::{Context, Result};
+use filetime::FileTime;
+
+/// Time set by `--set-times fixed`: 2000-01-01 00:00:00 UTC
+const FIXED_TIME: i64 = 946_684_800;
+
+/// Extended attributes that record where a file was downloaded from
+const SOURCE_XATTRS: [&str; 8] = [
+    "user.xdg.origin.url",
+    "user.xdg.referrer.url",
+    "user.xdg.origin.email.from",
+    "user.xdg.origin.email.subject",
+    "user.xdg.origin.email.message-id",
+    "user.xdg.publisher",
+    "com.apple.quarantine",
+    "com.apple.metadata:kMDItemWhereFroms",
+];
+
+/// Access and modification times given to cleaned files
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum TimesMode {
+    /// A fixed, meaningless date (2000-01-01 00:00:00 UTC)
+    Fixed,
+    /// The time of the clean
+    Now,
+    /// The Unix epoch (1970-01-01 00:00:00 UTC)
+    Epoch,
+}
+
+/// What happens to the extended attributes of the original
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum XattrMode {
+    /// Copy them all to the cleaned file
+    Keep,
+    /// Copy them, except the ones recording where the file came from (download URL, quarantine)
+    #[default]
+    Clean,
+    /// Copy none
+    Strip,
+}
+
+/// Filesystem attributes of cleaned files: timestamps and extended attributes
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct AttrArgs {
+    /// Give cleaned files the access and modification times of the original
+    #[arg(long, conflicts_with = "set_times")]
+    pub preserve_times: bool,
+    /// Set the access and modification times of cleaned files, hiding when the photo was taken or copied
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub set_times: Option<TimesMode>,
+    /// Extended attributes of the original to carry over (default: clean)
+    #[arg(long, value_enum, default_value_t = XattrMode::Clean)]
+    pub xattrs: XattrMode,
+}
+
+/// Attributes read from an original before it is cleaned (and maybe overwritten)
+pub struct Captured {
+    times: Option<(FileTime, FileTime)>,
+    xattrs: Vec<(OsString, Vec<u8>)>,
+}
+
+impl AttrArgs {
+    /// Read what has to be carried over from the original. Extended attributes that cannot
+    /// be read (e.g. on NFS or FUSE mounts without support for them) are skipped with a warning.
+    pub fn capture(&self, original: &Path) -> Result<Captured> {
+        let times = match (self.preserve_times, self.set_times) {
+            (true, _) => {
+                let metadata = std::fs::metadata(original)?;
+                Some((FileTime::from_last_access_time(&metadata), FileTime::from_last_modification_time(&metadata)))
+            }
+            (false, Some(mode)) => {
+                let time = match mode {
+                    TimesMode::Fixed => FileTime::from_unix_time(FIXED_TIME, 0),
+                    TimesMode::Now => FileTime::now(),
+                    TimesMode::Epoch => FileTime::zero(),
+                };
+                Some((time, time))
+            }
+            (false, None) => None,
+        };
+        let mut xattrs = Vec::new();
+        if self.xattrs != XattrMode::Strip && xattr::SUPPORTED_PLATFORM {
+            let names = match xattr::list(original) {
+                Ok(names) => names.collect(),
+                Err(e) => {
+                    log::warn!("Failed to list the extended attributes of {}: {}", original.display(), e);
+                    Vec::new()
+                }
+            };
+            for name in names {
+                if self.xattrs == XattrMode::Clean && SOURCE_XATTRS.iter().any(|s| name == *s) {
+                    continue;
+                }
+                match xattr::get(original, &name) {
+                    Ok(Some(value)) => xattrs.push((name, value)),
+                    Ok(None) => {}
+                    Err(e) => {
+                        log::warn!("Failed to read extended attribute {} of {}: {}", name.to_string_lossy(), original.display(), e)
+                    }
+                }
+            }
+        }
+        Ok(Captured { times, xattrs })
+    }
+}
+
+impl Captured {
+    /// Apply the attributes to the cleaned file. The times come last, as setting attributes
+    /// may touch them.
+    pub fn apply(&self, path: &Path) -> Result<()> {
+        for (name, value) in &self.xattrs {
+            // Some namespaces (security.*, trusted.*) need privileges; they are not worth failing the clean
+            if let Err(e) = xattr::set(path, name, value) {
+                log::warn!("Failed to copy extended attribute {} to {}: {}", name.to_string_lossy(), path.display(), e);
+            }
+        }
+        if let Some((accessed, modified)) = self.times {
+            filetime::set_file_times(path, accessed, modified)
+                .with_context(|| format!("Failed to set the times of {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An original with known times, and a cleaned file written after it
+    fn sample_files(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let (original, cleaned) = (dir.join("original.jpg"), dir.join("cleaned.jpg"));
+        std::fs::write(&original, b"original").unwrap();
+        std::fs::write(&cleaned, b"cleaned").unwrap();
+        let (accessed, modified) = (FileTime::from_unix_time(1_500_000_000, 0), FileTime::from_unix_time(1_600_000_000, 0));
+        filetime::set_file_times(&original, accessed, modified).unwrap();
+        (original, cleaned)
+    }
+
+    fn times(path: &Path) -> (i64, i64) {
+        let metadata = std::fs::metadata(path).unwrap();
+        let (accessed, modified) = (FileTime::from_last_access_time(&metadata), FileTime::from_last_modification_time(&metadata));
+        (accessed.unix_seconds(), modified.unix_seconds())
+    }
+
+    #[test]
+    fn times_are_preserved_or_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let (original, cleaned) = sample_files(dir.path());
+        let preserve = AttrArgs { preserve_times: true, ..Default::default() };
+        preserve.capture(&original).unwrap().apply(&cleaned).unwrap();
+        assert_eq!(times(&cleaned), (1_500_000_000, 1_600_000_000));
+
+        for (mode, expected) in [(TimesMode::Fixed, FIXED_TIME), (TimesMode::Epoch, 0)] {
+            let set = AttrArgs { set_times: Some(mode), ..Default::default() };
+            set.capture(&original).unwrap().apply(&cleaned).unwrap();
+            assert_eq!(times(&cleaned), (expected, expected));
+        }
+        let set = AttrArgs { set_times: Some(TimesMode::Now), ..Default::default() };
+        set.capture(&original).unwrap().apply(&cleaned).unwrap();
+        assert!(times(&cleaned).1 > 1_600_000_000);
+
+        // Without a times option the cleaned file keeps the times it was written with
+        let before = times(&cleaned);
+        AttrArgs::default().capture(&original).unwrap().apply(&cleaned).unwrap();
+        assert_eq!(times(&cleaned), before);
+    }
+
+    #[test]
+    fn clean_drops_the_download_source_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (original, cleaned) = sample_files(dir.path());
+        // Not every filesystem supports user attributes
+        if xattr::set(&original, "user.xdg.origin.url", b"https://example.com").is_err() {
+            return;
+        }
+        xattr::set(&original, "user.comment", b"holidays").unwrap();
+        AttrArgs::default().capture(&original).unwrap().apply(&cleaned).unwrap();
+        assert_eq!(xattr::get(&cleaned, "user.comment").unwrap().as_deref(), Some(&b"holidays"[..]));
+        assert_eq!(xattr::get(&cleaned, "user.xdg.origin.url").unwrap(), None);
+    }
+}
@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+use crate::atomic;
+use crate::logger::LogEntry;
+
+/// Copies of the originals overwritten by `clean --backup`, under the medars cache dir.
+/// Each file is named after the SHA-256 of its content, so identical originals are stored once.
+pub struct BackupStore {
+    dir: PathBuf,
+}
+
+/// What `prune` removed and what is left
+pub struct PruneStats {
+    pub removed: usize,
+    pub removed_bytes: u64,
+    pub kept: usize,
+    pub kept_bytes: u64,
+}
+
+impl BackupStore {
+    pub fn new() -> Self {
+        let mut dir = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
+        dir.push("medars");
+        dir.push("backups");
+        BackupStore { dir }
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    /// Back up a file, then run `replace`, which overwrites it, and return its result with the
+    /// hash of the backup. If `replace` fails the original is still in place, so a copy made
+    /// for this call alone is removed again: the store only holds backups the log points to.
+    pub fn with_backup<T>(&self, path: &Path, replace: impl FnOnce() -> Result<T>) -> Result<(T, String)> {
+        let (hash, created) = self.save(path)?;
+        match replace() {
+            Ok(value) => Ok((value, hash)),
+            Err(e) => {
+                if created {
+                    if let Err(remove) = std::fs::remove_file(self.path(&hash)) {
+                        log::warn!("Failed to remove the unused backup {}: {}", hash, remove);
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Store a copy of a file and return its content hash, and whether the copy is new
+    /// rather than shared with an identical original backed up before
+    fn save(&self, path: &Path) -> Result<(String, bool)> {
+        let data = std::fs::read(path)?;
+        let hash = sha256_hex(&data);
+        let backup = self.path(&hash);
+        if backup.exists() {
+            // Refresh the age, so pruning by age does not drop a backup that was just needed again
+            let _ = std::fs::File::options().append(true).open(&backup).and_then(|f| f.set_modified(SystemTime::now()));
+            return Ok((hash, false));
+        }
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create the backup directory {}", self.dir.display()))?;
+        let write = |temp: &Path| std::fs::write(temp, &data).context("Failed to write the backup");
+        atomic::write_file(&backup, path, write, |_| Ok(()))
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+        Ok((hash, true))
+    }
+
+    /// Write the backup with the given hash over `target`, checking its content first
+    pub fn restore(&self, hash: &str, target: &Path) -> Result<()> {
+        let backup = self.path(hash);
+        let data = std::fs::read(&backup)
+            .with_context(|| format!("Backup {} not found (it may have been pruned)", hash))?;
+        if sha256_hex(&data) != hash {
+            anyhow::bail!("Backup {} is corrupted", hash);
+        }
+        let write = |temp: &Path| std::fs::write(temp, &data).context("Failed to write the restored file");
+        atomic::write_file(target, &backup, write, |_| Ok(()))
+    }
+
+    /// Remove backups older than `max_age`, then the oldest ones until the store fits in `max_size` bytes
+    pub fn prune(&self, max_size: Option<u64>, max_age: Option<Duration>) -> Result<PruneStats> {
+        let mut backups = Vec::new();
+        if self.dir.exists() {
+            for entry in std::fs::read_dir(&self.dir)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                // Skip leftovers of interrupted writes (dot files) and anything else that is not a backup
+                if metadata.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    backups.push((modified, metadata.len(), entry.path()));
+                }
+            }
+        }
+        // Newest first: the oldest backups are the first to go
+        backups.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+        let now = SystemTime::now();
+        let mut stats = PruneStats { removed: 0, removed_bytes: 0, kept: 0, kept_bytes: 0 };
+        let mut full = false;
+        for (modified, size, path) in backups {
+            let too_old = max_age.is_some_and(|age| now.duration_since(modified).unwrap_or_default() > age);
+            full = full || max_size.is_some_and(|max| stats.kept_bytes + size > max);
+            if too_old || full {
+                std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+                stats.removed += 1;
+                stats.removed_bytes += size;
+            } else {
+                stats.kept += 1;
+                stats.kept_bytes += size;
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Lowercase hex SHA-256 of some data
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse the time of `undo --since`: RFC 3339 (`2024-05-01T12:00:00Z`) or a local date (`2024-05-01`)
+pub fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| format!("invalid time '{}' (expected e.g. 2024-05-01 or 2024-05-01T12:00:00Z)", value))
+}
+
+/// Which backups `undo` restores
+pub enum UndoSelection {
+    /// The most recently cleaned file
+    Last,
+    /// The state of a file before its last clean
+    File(PathBuf),
+    /// The state of every file before the first clean since a time
+    Since(DateTime<Utc>),
+}
+
+/// Log entries of the backups to restore, at most one per file, in log order
+pub fn select_undo(entries: Vec<LogEntry>, selection: &UndoSelection) -> Vec<LogEntry> {
+    let mut backed_up = entries
+        .into_iter()
+        .filter(|e| e.action == "clean" && e.result == "success" && e.backup.is_some());
+    match selection {
+        UndoSelection::Last => backed_up.next_back().into_iter().collect(),
+        UndoSelection::File(file) => backed_up.rfind(|e| Path::new(&e.file) == file).into_iter().collect(),
+        UndoSelection::Since(since) => {
+            let mut selected: Vec<LogEntry> = Vec::new();
+            for entry in backed_up.filter(|e| e.timestamp >= *since) {
+                if !selected.iter().any(|e| e.file == entry.file) {
+                    selected.push(entry);
+                }
+            }
+            selected
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(file: &str, minute: u32, backup: Option<&str>) -> LogEntry {
+        LogEntry {
+            timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, minute, 0).unwrap(),
+            action: "clean".to_string(),
+            file: file.to_string(),
+            result: "success".to_string(),
+            details: None,
+            backup: backup.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn undo_restores_the_oldest_backup_since_a_time() {
+        let entries = vec![
+            entry("/photos/a.jpg", 0, Some("a0")),
+            entry("/photos/a.jpg", 10, Some("a1")),
+            entry("/photos/b.jpg", 20, None),
+            entry("/photos/a.jpg", 30, Some("a2")),
+            entry("/photos/c.jpg", 40, Some("c0")),
+        ];
+        let hashes = |selected: Vec<LogEntry>| selected.into_iter().filter_map(|e| e.backup).collect::<Vec<_>>();
+        let since = UndoSelection::Since(Utc.with_ymd_and_hms(2024, 5, 1, 12, 5, 0).unwrap());
+        assert_eq!(hashes(select_undo(entries.clone(), &since)), ["a1", "c0"]);
+        assert_eq!(hashes(select_undo(entries.clone(), &UndoSelection::Last)), ["c0"]);
+        let file = UndoSelection::File(PathBuf::from("/photos/a.jpg"));
+        assert_eq!(hashes(select_undo(entries, &file)), ["a2"]);
+    }
+
+    #[test]
+    fn failed_cleans_leave_no_backup_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BackupStore { dir: dir.path().join("backups") };
+        let file = dir.path().join("photo.jpg");
+        std::fs::write(&file, b"original").unwrap();
+        let backups = || std::fs::read_dir(&store.dir).map(|d| d.count()).unwrap_or(0);
+
+        let failed = store.with_backup(&file, || -> Result<()> { anyhow::bail!("verification failed") });
+        assert!(failed.is_err());
+        assert_eq!(backups(), 0);
+
+        let ((), hash) = store.with_backup(&file, || Ok(())).unwrap();
+        assert_eq!(hash, sha256_hex(b"original"));
+        // A failure on an identical original keeps the backup the first clean made
+        assert!(store.with_backup(&file, || -> Result<()> { anyhow::bail!("verification failed") }).is_err());
+        assert_eq!(backups(), 1);
+        let restored = dir.path().join("restored.jpg");
+        store.restore(&hash, &restored).unwrap();
+        assert_eq!(std::fs::read(&restored).unwrap(), b"original");
+    }
+}
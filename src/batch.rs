@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Number of worker threads when `--jobs` is not given: one per CPU
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Run `task` on every item using `jobs` worker threads, and hand the results to `on_result`
+/// on the calling thread in the order of `items`, as soon as all earlier items are done.
+pub fn run_ordered<T, R, F, G>(items: &[T], jobs: usize, task: F, mut on_result: G)
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    G: FnMut(&T, R),
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            let sender = sender.clone();
+            let (next, task) = (&next, &task);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                if sender.send((index, task(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        // Results arrive in completion order; hold them back until their turn
+        let mut pending = BTreeMap::new();
+        let mut emitted = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&emitted) {
+                on_result(&items[emitted], result);
+                emitted += 1;
+            }
+        }
+    });
+}
+
+/// Progress bar of a batch: files done, bytes processed and ETA
+pub struct Progress {
+    bar: ProgressBar,
+    files: usize,
+    done: Mutex<usize>,
+}
+
+impl Progress {
+    pub fn new(files: &[PathBuf]) -> Self {
+        let total = files.iter().filter_map(|f| std::fs::metadata(f).ok()).map(|m| m.len()).sum();
+        let bar = ProgressBar::new(total);
+        bar.set_style(
+            ProgressStyle::with_template("{wide_bar} {msg} {binary_bytes}/{binary_total_bytes} ETA {eta}")
+                .unwrap_or_else(|_| ProgressStyle::default_bar()),
+        );
+        let progress = Progress { bar, files: files.len(), done: Mutex::new(0) };
+        progress.bar.set_message(format!("0/{} files", progress.files));
+        progress
+    }
+
+    /// Count a file of `bytes` bytes as processed; callable from the worker threads
+    pub fn file_done(&self, bytes: u64) {
+        // Update both counters together, so they never go backwards
+        let mut done = self.done.lock().unwrap_or_else(|e| e.into_inner());
+        *done += 1;
+        self.bar.set_message(format!("{}/{} files", *done, self.files));
+        self.bar.inc(bytes);
+    }
+
+    /// Print a line above the bar without garbling it
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.bar.suspend(f)
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Size of a file before processing it, for the progress bar
+pub fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |m| m.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn run_ordered_emits_results_in_item_order() {
+        let items: Vec<u64> = (0..8).collect();
+        let finished = Mutex::new(Vec::new());
+        let mut emitted = Vec::new();
+        // Earlier items sleep longer, so they finish after the later ones
+        run_ordered(
+            &items,
+            4,
+            |&item| {
+                std::thread::sleep(Duration::from_millis((8 - item) * 15));
+                finished.lock().unwrap().push(item);
+                item * 10
+            },
+            |&item, result| emitted.push((item, result)),
+        );
+        let finished = finished.into_inner().unwrap();
+        assert_ne!(finished, items);
+        assert_eq!(emitted, items.iter().map(|&i| (i, i * 10)).collect::<Vec<_>>());
+
+        let mut emitted = Vec::new();
+        run_ordered(&[] as &[u64], 4, |&item| item, |&item, _| emitted.push(item));
+        assert!(emitted.is_empty());
+    }
+}
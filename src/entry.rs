@@ -0,0 +1,164 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::formats::Container;
+use crate::sensitivity::{classify_key, Sensitivity};
+
+/// Group of the file properties (size, modification time, dimensions), which belong to no container
+pub const FILE_GROUP: &str = "File";
+
+/// A typed metadata value. Serialized untagged, so JSON carries plain numbers, strings and arrays.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Rational { num: i64, den: i64 },
+    Text(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// Convert an EXIF value. Undefined values have no generic structure (versions, maker
+    /// notes, ...) and are represented by their display text.
+    pub fn from_exif(value: &exif::Value, display: &str) -> Self {
+        use exif::Value as V;
+        let items: Vec<Value> = match value {
+            V::Byte(v) => v.iter().map(|&n| Value::Integer(n as i64)).collect(),
+            V::SByte(v) => v.iter().map(|&n| Value::Integer(n as i64)).collect(),
+            V::Short(v) => v.iter().map(|&n| Value::Integer(n as i64)).collect(),
+            V::SShort(v) => v.iter().map(|&n| Value::Integer(n as i64)).collect(),
+            V::Long(v) => v.iter().map(|&n| Value::Integer(n as i64)).collect(),
+            V::SLong(v) => v.iter().map(|&n| Value::Integer(n as i64)).collect(),
+            V::Ascii(v) => v
+                .iter()
+                .map(|s| Value::Text(String::from_utf8_lossy(s).trim_end_matches('\0').to_string()))
+                .collect(),
+            V::Rational(v) => v.iter().map(|r| Value::Rational { num: r.num as i64, den: r.denom as i64 }).collect(),
+            V::SRational(v) => v.iter().map(|r| Value::Rational { num: r.num as i64, den: r.denom as i64 }).collect(),
+            V::Float(v) => v.iter().map(|&f| Value::Float(f as f64)).collect(),
+            V::Double(v) => v.iter().map(|&f| Value::Float(f)).collect(),
+            _ => return Value::Text(display.to_string()),
+        };
+        Value::from_list(items)
+    }
+
+    /// A single item stands for itself, several make a list
+    pub fn from_list(mut items: Vec<Value>) -> Self {
+        if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            Value::List(items)
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Rational { num, den } => write!(f, "{}/{}", num, den),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|v| v.to_string()).collect();
+                write!(f, "{}", items.join(", "))
+            }
+        }
+    }
+}
+
+/// One metadata field, with where it was found and how sensitive it is
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetadataEntry {
+    /// Container the field was read from; `None` for file properties and other chunks
+    pub container: Option<Container>,
+    /// IFD, namespace, record or chunk of the field (`Image`, `GPSInfo`, `dc`, `Application2`, `tEXt`, ...)
+    pub group: String,
+    /// Numeric identifier, for formats that have one (EXIF/TIFF tags, ICC tag signatures)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_id: Option<u32>,
+    /// Field name (`GPSLatitude`, `creator`, `Software`)
+    pub name: String,
+    /// Full key, used for display and matched by tag patterns (`Exif.GPSInfo.GPSLatitude`, `Xmp.dc.creator`)
+    pub key: String,
+    pub value: Value,
+    /// Human-readable value, with units where known (`1/100 s`, `f/2.8`)
+    pub display: String,
+    /// Bytes of binary values, hex-encoded in JSON
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex")]
+    pub raw: Option<Vec<u8>>,
+    pub sensitivity: Sensitivity,
+}
+
+impl MetadataEntry {
+    /// An entry displayed as its value; the sensitivity is classified from the key
+    pub fn new(container: Option<Container>, group: &str, name: &str, key: String, value: Value) -> Self {
+        MetadataEntry {
+            container,
+            group: group.to_string(),
+            tag_id: None,
+            name: name.to_string(),
+            sensitivity: classify_key(&key),
+            key,
+            display: value.to_string(),
+            value,
+            raw: None,
+        }
+    }
+
+    /// A file property (`File Size`, `Modified`, `Dimensions`)
+    pub fn file(name: &str, value: Value, display: String) -> Self {
+        MetadataEntry::new(None, FILE_GROUP, name, name.to_string(), value).with_display(display)
+    }
+
+    pub fn with_tag_id(mut self, tag_id: u32) -> Self {
+        self.tag_id = Some(tag_id);
+        self
+    }
+
+    pub fn with_display(mut self, display: String) -> Self {
+        self.display = display;
+        self
+    }
+
+    pub fn with_raw(mut self, raw: Vec<u8>) -> Self {
+        self.raw = Some(raw);
+        self
+    }
+
+    /// Whether the entry is a GPS field (`Exif.GPSInfo.*`, `Xmp.exif.GPSLatitude`, ...)
+    pub fn is_gps(&self) -> bool {
+        self.group == "GPSInfo" || self.name.starts_with("GPS")
+    }
+
+    /// Whether the entry is a file property rather than embedded metadata
+    pub fn is_file_property(&self) -> bool {
+        self.container.is_none() && self.group == FILE_GROUP
+    }
+}
+
+/// Hex encoding of raw bytes
+mod hex {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(raw: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match raw {
+            Some(bytes) => serializer.serialize_str(&bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        let Some(hex) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(D::Error::custom("invalid hex string"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(D::Error::custom))
+            .collect::<Result<Vec<u8>, _>>()
+            .map(Some)
+    }
+}
@@ -0,0 +1,377 @@
+use std::borrow::Cow;
+use anyhow::{bail, Context, Result};
+use super::icc::{self, Rewrite};
+use super::{iptc, xmp, Container};
+use crate::entry::{MetadataEntry, Value};
+use crate::policy::RemovalPolicy;
+
+pub const GIF87A: &[u8; 6] = b"GIF87a";
+pub const GIF89A: &[u8; 6] = b"GIF89a";
+
+const EXTENSION: u8 = 0x21;
+const IMAGE_DESCRIPTOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+
+const LABEL_COMMENT: u8 = 0xFE;
+const LABEL_APPLICATION: u8 = 0xFF;
+
+/// Application identifiers and authentication codes (11 bytes) of metadata extensions
+const APP_XMP: &[u8; 11] = b"XMP DataXMP";
+const APP_ICC: &[u8; 11] = b"ICCRGBG1012";
+const APP_IPTC: &[u8; 11] = b"MGKIPTC0000";
+const APP_PHOTOSHOP: &[u8; 11] = b"MGK8BIM0000";
+
+/// Length of the "magic trailer" that follows XMP data so it can be skipped as sub-blocks
+const XMP_MAGIC_TRAILER_LEN: usize = 257;
+
+/// A block of a GIF data stream: an extension, or an image (descriptor, color table and data)
+pub struct Block<'a> {
+    /// Extension label, `None` for images
+    pub label: Option<u8>,
+    /// Identifier and authentication code of application extensions
+    pub application: Option<&'a [u8]>,
+    /// Raw bytes of the whole block, including the sub-block terminator
+    pub bytes: &'a [u8],
+    /// Offset of the first data sub-block within `bytes`
+    data_start: usize,
+}
+
+impl<'a> Block<'a> {
+    /// Metadata container carried by this block, if any
+    pub fn container(&self) -> Option<Container> {
+        match (self.label, self.application) {
+            (Some(LABEL_COMMENT), _) => Some(Container::Comment),
+            (Some(LABEL_APPLICATION), Some(app)) if app == APP_XMP => Some(Container::Xmp),
+            (Some(LABEL_APPLICATION), Some(app)) if app == APP_ICC => Some(Container::Icc),
+            (Some(LABEL_APPLICATION), Some(app)) if app == APP_IPTC || app == APP_PHOTOSHOP => {
+                Some(Container::Iptc)
+            }
+            _ => None,
+        }
+    }
+
+    /// Concatenated payload of the data sub-blocks
+    pub fn data(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pos = self.data_start;
+        while let Some(&len) = self.bytes.get(pos) {
+            if len == 0 {
+                break;
+            }
+            let end = (pos + 1 + len as usize).min(self.bytes.len());
+            out.extend_from_slice(&self.bytes[pos + 1..end]);
+            pos = end;
+        }
+        out
+    }
+
+    /// XMP packet of an `XMP DataXMP` extension. The packet is stored as raw bytes (its
+    /// characters double as sub-block lengths) followed by a magic trailer.
+    pub fn xmp(&self) -> Option<&'a [u8]> {
+        let raw = &self.bytes[self.data_start..self.bytes.len() - 1];
+        raw.get(..raw.len().checked_sub(XMP_MAGIC_TRAILER_LEN)?)
+    }
+}
+
+/// A parsed GIF: header, logical screen descriptor and global color table, then the blocks
+pub struct Gif<'a> {
+    pub header: &'a [u8],
+    pub blocks: Vec<Block<'a>>,
+    /// Offset just past the trailer byte; anything after it is trailing data
+    pub end: usize,
+}
+
+impl<'a> Gif<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if data.len() < 13 || !(data.starts_with(GIF87A) || data.starts_with(GIF89A)) {
+            bail!("Not a GIF file");
+        }
+        let mut pos = 13 + color_table_len(data[10]);
+        let header = data.get(..pos).ok_or_else(|| anyhow::anyhow!("Truncated GIF header"))?;
+        let mut blocks = Vec::new();
+        loop {
+            let start = pos;
+            let (label, application, data_start) = match data.get(pos) {
+                Some(&EXTENSION) => {
+                    let label = *data.get(pos + 1).ok_or_else(|| truncated(pos))?;
+                    let application = (label == LABEL_APPLICATION && data.get(pos + 2) == Some(&11))
+                        .then(|| data.get(pos + 3..pos + 14))
+                        .flatten();
+                    let data_start = if application.is_some() { pos + 14 } else { pos + 2 };
+                    (Some(label), application, data_start)
+                }
+                Some(&IMAGE_DESCRIPTOR) => {
+                    let flags = *data.get(pos + 9).ok_or_else(|| truncated(pos))?;
+                    // Descriptor, local color table, then the LZW minimum code size
+                    (None, None, pos + 10 + color_table_len(flags) + 1)
+                }
+                Some(&TRAILER) => {
+                    pos += 1;
+                    break;
+                }
+                // A missing trailer is common enough in the wild to accept
+                None => break,
+                Some(b) => bail!("Unexpected GIF block 0x{:02X} at offset {}", b, pos),
+            };
+            pos = skip_sub_blocks(data, data_start).ok_or_else(|| truncated(start))?;
+            blocks.push(Block { label, application, bytes: &data[start..pos], data_start: data_start - start });
+        }
+        Ok(Gif { header, blocks, end: pos })
+    }
+
+    /// Bytes after the trailer
+    pub fn trailer(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.end..]
+    }
+}
+
+/// Detect the metadata containers in a GIF
+pub fn containers(data: &[u8]) -> Result<Vec<Container>> {
+    let gif = Gif::parse(data)?;
+    let mut found: Vec<Container> = gif.blocks.iter().filter_map(|b| b.container()).collect();
+    if !gif.trailer(data).is_empty() {
+        found.push(Container::Trailer);
+    }
+    Ok(found)
+}
+
+/// Comment extensions and metadata application extensions, as display key/value pairs.
+/// XMP is left to the XMP reader (see [`xmp_packet`]).
+pub fn block_entries(data: &[u8]) -> Result<Vec<MetadataEntry>> {
+    let gif = Gif::parse(data)?;
+    let mut entries = Vec::new();
+    let mut comments = 0;
+    for block in &gif.blocks {
+        match block.container() {
+            Some(Container::Comment) => {
+                comments += 1;
+                let key = comment_key(comments);
+                let text = String::from_utf8_lossy(&block.data()).trim_end_matches('\0').to_string();
+                entries.push(MetadataEntry::new(Some(Container::Comment), "Comment", "Comment", key, Value::Text(text)));
+            }
+            container @ Some(Container::Icc | Container::Iptc) => {
+                let app = String::from_utf8_lossy(block.application.unwrap_or_default()).into_owned();
+                let len = block.data().len();
+                let key = format!("Gif.Application.{}", app);
+                entries.push(
+                    MetadataEntry::new(container, "Application", &app, key, Value::Integer(len as i64))
+                        .with_display(format!("{} bytes", len)),
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// XMP packet of the `XMP DataXMP` extension, if any
+pub fn xmp_packet(data: &[u8]) -> Option<Vec<u8>> {
+    let gif = Gif::parse(data).ok()?;
+    let block = gif.blocks.iter().find(|b| b.container() == Some(Container::Xmp))?;
+    block.xmp().map(|xmp| xmp.to_vec())
+}
+
+/// ICC profile of the `ICCRGBG1012` extension, if any
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let gif = Gif::parse(data).ok()?;
+    gif.blocks.iter().find(|b| b.container() == Some(Container::Icc)).map(|b| b.data())
+}
+
+/// Key of the n-th comment extension (1-based), as listed by [`block_entries`]
+fn comment_key(n: usize) -> String {
+    if n == 1 {
+        "Gif.Comment".to_string()
+    } else {
+        format!("Gif.Comment[{}]", n)
+    }
+}
+
+/// Rewrite a GIF with the XMP and IPTC extensions reduced to the properties and datasets
+/// the policy keeps, and comments kept or removed one by one. The ICC profile extension is
+/// kept, stripped or replaced following the policy's ICC mode; frames, graphic control
+/// extensions and other application extensions (NETSCAPE2.0 looping) are copied as they
+/// are. Data after the trailer byte is dropped unless the policy keeps it.
+pub fn clean(data: &[u8], policy: &RemovalPolicy) -> Result<Vec<u8>> {
+    let gif = Gif::parse(data)?;
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(gif.header);
+    let mut comments = 0;
+    for block in &gif.blocks {
+        match block.container() {
+            Some(Container::Icc) => match icc::rewrite(&block.data(), policy.icc_mode()) {
+                Rewrite::Keep => out.extend_from_slice(block.bytes),
+                Rewrite::Strip => {}
+                Rewrite::Replace(profile) => write_application(&mut out, APP_ICC, &profile),
+            },
+            Some(Container::Comment) => {
+                comments += 1;
+                if !policy.should_remove(&comment_key(comments)) {
+                    out.extend_from_slice(block.bytes);
+                }
+            }
+            Some(Container::Xmp) if !policy.removes_everything() => {
+                // A packet without its magic trailer cannot be read, so it goes as a whole
+                let Some(packet) = block.xmp() else {
+                    continue;
+                };
+                let packet = xmp::filter(packet, policy)
+                    .context("Failed to filter the XMP packet (--level all removes it as a whole)")?;
+                match packet {
+                    Some(Cow::Borrowed(_)) => out.extend_from_slice(block.bytes),
+                    Some(Cow::Owned(packet)) => write_xmp(&mut out, &packet),
+                    None => {}
+                }
+            }
+            Some(Container::Iptc) if !policy.removes_everything() => {
+                let filtered = if block.application == Some(APP_IPTC.as_slice()) {
+                    iptc::filter_records(&block.data(), policy)
+                } else {
+                    iptc::filter_resources(&block.data(), policy)
+                };
+                let filtered = filtered.context("Failed to filter the IPTC records (--level all removes them as a whole)")?;
+                if let (Some(filtered), Some(app)) = (filtered, block.application) {
+                    write_application(&mut out, app.try_into()?, &filtered);
+                }
+            }
+            Some(_) => {}
+            None => out.extend_from_slice(block.bytes),
+        }
+    }
+    out.push(TRAILER);
+    if policy.keeps_trailer() {
+        out.extend_from_slice(gif.trailer(data));
+    }
+    Ok(out)
+}
+
+/// Append an application extension carrying `data` in sub-blocks
+fn write_application(out: &mut Vec<u8>, application: &[u8; 11], data: &[u8]) {
+    out.extend_from_slice(&[EXTENSION, LABEL_APPLICATION, 11]);
+    out.extend_from_slice(application);
+    for sub_block in data.chunks(255) {
+        out.push(sub_block.len() as u8);
+        out.extend_from_slice(sub_block);
+    }
+    out.push(0);
+}
+
+/// Append an `XMP DataXMP` extension: the packet as raw bytes, then the magic trailer that
+/// lets readers unaware of XMP skip it as sub-blocks
+fn write_xmp(out: &mut Vec<u8>, packet: &[u8]) {
+    out.extend_from_slice(&[EXTENSION, LABEL_APPLICATION, 11]);
+    out.extend_from_slice(APP_XMP);
+    out.extend_from_slice(packet);
+    out.push(1);
+    out.extend((0..=255).rev());
+    out.push(0);
+}
+
+/// Size of the color table announced by a packed flags byte
+fn color_table_len(flags: u8) -> usize {
+    if flags & 0x80 != 0 {
+        3 << ((flags & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+/// Position just past the terminator of the sub-blocks starting at `pos`
+fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(pos)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return Some(pos);
+        }
+    }
+}
+
+fn truncated(pos: usize) -> anyhow::Error {
+    anyhow::anyhow!("Truncated GIF block at offset {}", pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PrivacyLevel;
+    use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+    use image::{AnimationDecoder, Delay, Frame, Rgba, RgbaImage};
+
+    const LABEL_GRAPHIC_CONTROL: u8 = 0xF9;
+
+    /// A looping two-frame animation with a comment and an XMP extension before the frames,
+    /// followed by trailing data
+    fn sample_gif() -> Vec<u8> {
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut encoded);
+            encoder.set_repeat(Repeat::Infinite).unwrap();
+            for shade in [0, 255] {
+                let image = RgbaImage::from_pixel(4, 4, Rgba([shade, 0, 0, 255]));
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(100, 1))).unwrap();
+            }
+        }
+        let gif = Gif::parse(&encoded).unwrap();
+        let mut out = gif.header.to_vec();
+        out.extend_from_slice(&[EXTENSION, LABEL_COMMENT, 5]);
+        out.extend_from_slice(b"hello\0");
+        write_xmp(&mut out, xmp::tests::PACKET);
+        for block in &gif.blocks {
+            out.extend_from_slice(block.bytes);
+        }
+        out.push(TRAILER);
+        out.extend_from_slice(b"trailing");
+        out
+    }
+
+    #[test]
+    fn clean_keeps_frames_and_animation_blocks() {
+        let original = sample_gif();
+        assert_eq!(containers(&original).unwrap(), [Container::Comment, Container::Xmp, Container::Trailer]);
+        assert_eq!(xmp_packet(&original).unwrap(), xmp::tests::PACKET);
+
+        // Only the orientation is left of the XMP packet
+        let policy = RemovalPolicy::new(None, &[], &[]).unwrap();
+        let cleaned = clean(&original, &policy).unwrap();
+        let before = Gif::parse(&original).unwrap();
+        let after = Gif::parse(&cleaned).unwrap();
+        assert_eq!(containers(&cleaned).unwrap(), [Container::Xmp]);
+        let blocks = |gif: &Gif<'_>| {
+            gif.blocks.iter().filter(|b| b.container().is_none()).map(|b| b.bytes.to_vec()).collect::<Vec<_>>()
+        };
+        assert_eq!(blocks(&after), blocks(&before));
+        assert_eq!(after.blocks.len(), before.blocks.len() - 1);
+        assert!(after.blocks.iter().any(|b| b.application == Some(b"NETSCAPE2.0".as_slice())));
+        assert_eq!(after.blocks.iter().filter(|b| b.label == Some(LABEL_GRAPHIC_CONTROL)).count(), 2);
+        assert_eq!(after.end, cleaned.len());
+
+        let frames = GifDecoder::new(std::io::Cursor::new(&cleaned)).unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].buffer().get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+
+        let keep_trailer = RemovalPolicy::new(None, &[], &[]).unwrap().with_trailer(true);
+        assert!(clean(&original, &keep_trailer).unwrap().ends_with(b"\x3Btrailing"));
+    }
+
+    #[test]
+    fn clean_keeps_the_comments_and_xmp_the_policy_keeps() {
+        let original = sample_gif();
+        let red = RemovalPolicy::new(Some(PrivacyLevel::Red), &[], &[]).unwrap();
+        let cleaned = clean(&original, &red).unwrap();
+        assert_eq!(containers(&cleaned).unwrap(), [Container::Comment, Container::Xmp]);
+        assert_eq!(block_entries(&cleaned).unwrap()[0].display, "hello");
+        let packet = String::from_utf8(xmp_packet(&cleaned).unwrap()).unwrap();
+        assert!(packet.contains("tiff:Orientation") && !packet.contains("GPSLatitude") && !packet.contains("dc:creator"));
+        assert_eq!(GifDecoder::new(std::io::Cursor::new(&cleaned)).unwrap().into_frames().count(), 2);
+
+        let keep_comment = RemovalPolicy::new(None, &["Comment".to_string()], &[]).unwrap();
+        let cleaned = clean(&original, &keep_comment).unwrap();
+        assert_eq!(containers(&cleaned).unwrap(), [Container::Comment]);
+
+        let strip_gps = RemovalPolicy::new(None, &[], &["GPS*".to_string()]).unwrap();
+        let cleaned = clean(&original, &strip_gps).unwrap();
+        assert_eq!(containers(&cleaned).unwrap(), [Container::Comment, Container::Xmp]);
+        let packet = String::from_utf8(xmp_packet(&cleaned).unwrap()).unwrap();
+        assert!(packet.contains("dc:creator") && !packet.contains("GPSLatitude"));
+    }
+}
@@ -0,0 +1,887 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use anyhow::{bail, Context, Result};
+use super::icc::{self, Rewrite};
+use super::thumbnail::{Thumbnail, ThumbnailKind};
+use super::{xmp, Container};
+use crate::policy::RemovalPolicy;
+
+/// ftyp brands of AVIF files (checked first, since AVIF files also list the generic `mif1`)
+const AVIF_BRANDS: [&[u8; 4]; 2] = [b"avif", b"avis"];
+/// ftyp brands of HEIF files (HEIC and the generic image/sequence brands)
+const HEIF_BRANDS: [&[u8; 4]; 8] = [b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Brand {
+    Heif,
+    Avif,
+}
+
+/// Detect HEIF/AVIF from the brands of the leading ftyp box
+pub fn brand(data: &[u8]) -> Option<Brand> {
+    if data.len() < 16 || &data[4..8] != b"ftyp" {
+        return None;
+    }
+    let size = (be32(data, 0) as usize).clamp(16, data.len());
+    // Major brand, then the compatible brands
+    let brands: Vec<&[u8]> = std::iter::once(&data[8..12]).chain(data[16..size].chunks_exact(4)).collect();
+    if brands.iter().any(|b| AVIF_BRANDS.iter().any(|a| a.as_slice() == *b)) {
+        Some(Brand::Avif)
+    } else if brands.iter().any(|b| HEIF_BRANDS.iter().any(|h| h.as_slice() == *b)) {
+        Some(Brand::Heif)
+    } else {
+        None
+    }
+}
+
+/// Position of an ISOBMFF box within the file
+#[derive(Clone, Copy)]
+struct BoxRef {
+    kind: [u8; 4],
+    start: usize,
+    /// Start of the box payload (after size, type and largesize)
+    body: usize,
+    end: usize,
+}
+
+/// An item declared in `iinf`
+struct Item {
+    id: u32,
+    item_type: [u8; 4],
+    content_type: String,
+}
+
+impl Item {
+    fn is_exif(&self) -> bool {
+        &self.item_type == b"Exif"
+    }
+
+    fn is_xmp(&self) -> bool {
+        &self.item_type == b"mime" && self.content_type == "application/rdf+xml"
+    }
+}
+
+/// Where an item's data lives, as declared in `iloc`
+struct Location {
+    id: u32,
+    construction_method: u16,
+    /// (offset, length) pairs, with the base offset already applied.
+    /// Offsets are absolute for construction method 0 and relative to `idat` for method 1.
+    extents: Vec<(u64, u64)>,
+}
+
+/// A parsed HEIF/AVIF file: the top-level boxes and the items of its `meta` box
+pub struct Heif {
+    meta: BoxRef,
+    items: Vec<Item>,
+    locations: Vec<Location>,
+    /// Offset just past the last top-level box; anything after it is trailing data
+    pub end: usize,
+}
+
+impl Heif {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if brand(data).is_none() {
+            bail!("Not a HEIF/AVIF file");
+        }
+        let (boxes, end) = parse_boxes_lenient(data, 0, data.len());
+        let meta = *boxes
+            .iter()
+            .find(|b| &b.kind == b"meta")
+            .context("HEIF file without a meta box")?;
+        let mut heif = Heif { meta, items: Vec::new(), locations: Vec::new(), end };
+        for child in heif.meta_children(data)? {
+            match &child.kind {
+                b"iinf" => heif.items = parse_iinf(data, &child)?,
+                b"iloc" => heif.locations = parse_iloc(data, &child)?,
+                _ => {}
+            }
+        }
+        Ok(heif)
+    }
+
+    /// Bytes after the last top-level box
+    pub fn trailer<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.end..]
+    }
+
+    fn meta_children(&self, data: &[u8]) -> Result<Vec<BoxRef>> {
+        // meta is a full box: version and flags come before the children
+        parse_boxes(data, self.meta.body + 4, self.meta.end)
+    }
+
+    fn child(&self, data: &[u8], kind: &[u8; 4]) -> Option<BoxRef> {
+        self.meta_children(data).ok()?.into_iter().find(|b| &b.kind == kind)
+    }
+
+    /// Concatenated data of an item, if it is stored in the file or in `idat`
+    fn item_data(&self, data: &[u8], id: u32) -> Option<Vec<u8>> {
+        let location = self.locations.iter().find(|l| l.id == id)?;
+        let mut out = Vec::new();
+        for range in self.extent_ranges(data, location).ok()? {
+            out.extend_from_slice(&data[range]);
+        }
+        Some(out)
+    }
+
+    /// Byte ranges of an item's extents in the file. Extents running past the end of the
+    /// file (or of `idat`) are cut short.
+    fn extent_ranges(&self, data: &[u8], location: &Location) -> Result<Vec<Range<usize>>> {
+        let (base, limit) = match location.construction_method {
+            0 => (0, data.len()),
+            1 => {
+                let idat = self.child(data, b"idat").context("HEIF item stored in a missing idat box")?;
+                (idat.body, idat.end)
+            }
+            method => bail!("Unsupported construction method {} of HEIF item {}", method, location.id),
+        };
+        let mut ranges = Vec::with_capacity(location.extents.len());
+        for &(offset, length) in &location.extents {
+            let start = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| base.checked_add(offset))
+                .filter(|&start| start <= limit)
+                .with_context(|| format!("Invalid extent of HEIF item {}", location.id))?;
+            let end = match usize::try_from(length) {
+                Ok(0) => limit,
+                Ok(length) => start.saturating_add(length).min(limit),
+                Err(_) => limit,
+            };
+            ranges.push(start..end);
+        }
+        Ok(ranges)
+    }
+
+    /// ICC profile from a `colr` property, if any
+    pub fn icc_profile(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.icc_colr(data).map(|b| data[b.body + 4..b.end].to_vec())
+    }
+
+    /// The `colr` property carrying an ICC profile (rather than nclx color information)
+    fn icc_colr(&self, data: &[u8]) -> Option<BoxRef> {
+        let iprp = self.child(data, b"iprp")?;
+        let ipco = parse_boxes(data, iprp.body, iprp.end).ok()?.into_iter().find(|b| &b.kind == b"ipco")?;
+        parse_boxes(data, ipco.body, ipco.end)
+            .ok()?
+            .into_iter()
+            .filter(|b| &b.kind == b"colr" && b.end - b.body > 4)
+            .find(|b| matches!(&data[b.body..b.body + 4], b"prof" | b"rICC"))
+    }
+
+    /// Items that are the thumbnail of another item (`thmb` references)
+    fn thumbnail_ids(&self, data: &[u8]) -> Result<Vec<u32>> {
+        let Some(iref) = self.child(data, b"iref") else {
+            return Ok(Vec::new());
+        };
+        let wide = Reader::new(data, iref.body, iref.end).bytes(4)?[0] != 0;
+        let mut ids = Vec::new();
+        for reference in parse_boxes(data, iref.body + 4, iref.end)? {
+            if &reference.kind == b"thmb" {
+                let mut r = Reader::new(data, reference.body, reference.end);
+                ids.push(if wide { r.u32()? } else { r.u16()? as u32 });
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Width and height from the `ispe` property associated with an item
+    fn item_dimensions(&self, data: &[u8], id: u32) -> Option<(u32, u32)> {
+        let iprp = self.child(data, b"iprp")?;
+        let children = parse_boxes(data, iprp.body, iprp.end).ok()?;
+        let ipco = children.iter().find(|b| &b.kind == b"ipco")?;
+        let properties = parse_boxes(data, ipco.body, ipco.end).ok()?;
+        for ipma in children.iter().filter(|b| &b.kind == b"ipma") {
+            let mut r = Reader::new(data, ipma.body, ipma.end);
+            let version = r.u8().ok()?;
+            let wide_index = r.bytes(3).ok()?[2] & 1 == 1;
+            for _ in 0..r.u32().ok()? {
+                let item = if version < 1 { r.u16().ok()? as u32 } else { r.u32().ok()? };
+                for _ in 0..r.u8().ok()? {
+                    // Property indices are 1-based; the top bit flags essential properties
+                    let index = if wide_index { r.u16().ok()? & 0x7FFF } else { (r.u8().ok()? & 0x7F) as u16 };
+                    let property = index.checked_sub(1).and_then(|i| properties.get(i as usize));
+                    if let Some(ispe) = property.filter(|p| item == id && &p.kind == b"ispe") {
+                        let mut r = Reader::new(data, ispe.body + 4, ispe.end);
+                        return Some((r.u32().ok()?, r.u32().ok()?));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Thumbnail items. Their data is a bare HEVC or AV1 stream, not a standalone image.
+pub fn thumbnails(data: &[u8]) -> Vec<Thumbnail> {
+    let Ok(heif) = Heif::parse(data) else {
+        return Vec::new();
+    };
+    heif.thumbnail_ids(data)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|id| {
+            Some(Thumbnail {
+                kind: ThumbnailKind::Heif,
+                dimensions: heif.item_dimensions(data, id),
+                data: heif.item_data(data, id)?,
+            })
+        })
+        .collect()
+}
+
+/// Detect the metadata containers in a HEIF/AVIF file
+pub fn containers(data: &[u8]) -> Result<Vec<Container>> {
+    let heif = Heif::parse(data)?;
+    let mut found = Vec::new();
+    for item in &heif.items {
+        if item.is_exif() {
+            found.push(Container::Exif);
+            if exif_tiff_of(&heif, data, item.id).is_some_and(|tiff| super::exif_has_thumbnail(&tiff)) {
+                found.push(Container::Thumbnail);
+            }
+        } else if item.is_xmp() {
+            found.push(Container::Xmp);
+        }
+    }
+    if heif.icc_profile(data).is_some() {
+        found.push(Container::Icc);
+    }
+    if !heif.thumbnail_ids(data)?.is_empty() {
+        found.push(Container::Thumbnail);
+    }
+    if !heif.trailer(data).is_empty() {
+        found.push(Container::Trailer);
+    }
+    Ok(found)
+}
+
+/// Raw TIFF block of the first Exif item, if any
+pub fn exif_tiff(data: &[u8]) -> Option<Vec<u8>> {
+    let heif = Heif::parse(data).ok()?;
+    let item = heif.items.iter().find(|i| i.is_exif())?;
+    exif_tiff_of(&heif, data, item.id)
+}
+
+/// The Exif item payload starts with the offset of the TIFF header
+fn exif_tiff_of(heif: &Heif, data: &[u8], id: u32) -> Option<Vec<u8>> {
+    let payload = heif.item_data(data, id)?;
+    let offset = be32(payload.get(..4)?, 0) as usize;
+    payload.get(4 + offset..).map(|tiff| tiff.to_vec())
+}
+
+/// XMP packet of the first XMP item, if any
+pub fn xmp_packet(data: &[u8]) -> Option<Vec<u8>> {
+    let heif = Heif::parse(data).ok()?;
+    let item = heif.items.iter().find(|i| i.is_xmp())?;
+    heif.item_data(data, item.id)
+}
+
+/// ICC profile from the `colr` property, if any
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    Heif::parse(data).ok()?.icc_profile(data)
+}
+
+/// Rewrite a HEIF/AVIF file without its thumbnail items, with the Exif and XMP items
+/// reduced to the tags and properties the policy keeps.
+///
+/// Removed items are dropped from `iinf`, `iloc`, `iref` and `ipma`, and their payload is
+/// zero-filled. The rebuilt `meta` box is followed by a `free` box of the size it lost, so
+/// no other box moves and every offset into `mdat` stays valid. A filtered Exif block is
+/// zero-padded, and a filtered XMP packet space-padded, to its old length and written over
+/// the old one for the same reason.
+/// A stripped ICC profile becomes a `free` box of the same size, so property indices stay
+/// valid; a replacement profile must fit in the space the `meta` box already has.
+/// Data after the last box is dropped unless the policy keeps it.
+pub fn clean(data: &[u8], policy: &RemovalPolicy) -> Result<Vec<u8>> {
+    let heif = Heif::parse(data)?;
+    let mut removed = Vec::new();
+    let mut rewritten = Vec::new();
+    for item in heif.items.iter().filter(|i| i.is_exif()) {
+        // The payload starts with the offset of the TIFF header; unreadable payloads are removed as a whole
+        let payload = heif.item_data(data, item.id).filter(|_| !policy.removes_everything()).and_then(|payload| {
+            let at = 4 + be32(payload.get(..4)?, 0) as usize;
+            (at <= payload.len()).then_some((payload, at))
+        });
+        let Some((payload, at)) = payload else {
+            removed.push(item.id);
+            continue;
+        };
+        match super::filter_exif(&payload[at..], policy)? {
+            None => removed.push(item.id),
+            Some(tiff) => {
+                let mut filtered = [&payload[..at], &tiff].concat();
+                if filtered.len() > payload.len() {
+                    bail!("Cannot rewrite the HEIF Exif item in place");
+                }
+                filtered.resize(payload.len(), 0);
+                rewritten.push((item.id, filtered));
+            }
+        }
+    }
+    for item in heif.items.iter().filter(|i| i.is_xmp()) {
+        // Unreadable packets are removed as a whole
+        let Some(packet) = heif.item_data(data, item.id).filter(|_| !policy.removes_everything()) else {
+            removed.push(item.id);
+            continue;
+        };
+        match xmp::filter(&packet, policy)? {
+            None => removed.push(item.id),
+            Some(Cow::Borrowed(_)) => {}
+            Some(Cow::Owned(filtered)) => {
+                let padded = xmp::pad(&filtered, packet.len()).context("Cannot rewrite the HEIF XMP item in place")?;
+                rewritten.push((item.id, padded));
+            }
+        }
+    }
+    removed.extend(heif.thumbnail_ids(data)?);
+    let colr = heif.icc_colr(data).and_then(|colr| {
+        let replacement = match icc::rewrite(&data[colr.body + 4..colr.end], policy.icc_mode()) {
+            Rewrite::Keep => return None,
+            Rewrite::Strip => write_box(b"free", &vec![0; colr.end - colr.start - 8]),
+            Rewrite::Replace(profile) => write_box(b"colr", &[b"prof".as_slice(), &profile].concat()),
+        };
+        Some((colr, replacement))
+    });
+    if removed.is_empty() && rewritten.is_empty() && colr.is_none() {
+        let end = if policy.keeps_trailer() { data.len() } else { heif.end };
+        return Ok(data[..end].to_vec());
+    }
+
+    let mut out = data.to_vec();
+    for location in heif.locations.iter().filter(|l| removed.contains(&l.id)) {
+        for range in heif.extent_ranges(data, location)? {
+            out[range].fill(0);
+        }
+    }
+    for (id, packet) in &rewritten {
+        let location = heif.locations.iter().find(|l| l.id == *id).context("HEIF item without a location")?;
+        let mut rest = packet.as_slice();
+        for range in heif.extent_ranges(data, location)? {
+            let (head, tail) = rest.split_at(range.len());
+            out[range].copy_from_slice(head);
+            rest = tail;
+        }
+    }
+
+    let mut children = Vec::new();
+    for child in heif.meta_children(&out)? {
+        let bytes = match &child.kind {
+            b"iinf" => rebuild_iinf(&out, &child, &removed)?,
+            b"iloc" => rebuild_iloc(&out, &child, &removed)?,
+            b"iref" => rebuild_iref(&out, &child, &removed)?,
+            b"iprp" => rebuild_iprp(&out, &child, &removed, colr.as_ref())?,
+            _ => out[child.start..child.end].to_vec(),
+        };
+        children.extend_from_slice(&bytes);
+    }
+    let mut meta_body = out[heif.meta.body..heif.meta.body + 4].to_vec();
+    meta_body.extend_from_slice(&children);
+    let meta = write_box(b"meta", &meta_body);
+
+    let old_len = heif.meta.end - heif.meta.start;
+    let padding = old_len
+        .checked_sub(meta.len())
+        .filter(|&p| p == 0 || p >= 8)
+        .context("Cannot rewrite the HEIF meta box in place")?;
+    let mut result = Vec::with_capacity(out.len());
+    result.extend_from_slice(&out[..heif.meta.start]);
+    result.extend_from_slice(&meta);
+    if padding > 0 {
+        result.extend_from_slice(&write_box(b"free", &vec![0; padding - 8]));
+    }
+    let end = if policy.keeps_trailer() { out.len() } else { heif.end };
+    result.extend_from_slice(&out[heif.meta.end..end]);
+    Ok(result)
+}
+
+fn parse_iinf(data: &[u8], iinf: &BoxRef) -> Result<Vec<Item>> {
+    let version = *data.get(iinf.body).context("Truncated iinf box")?;
+    let entries_at = iinf.body + 4 + if version == 0 { 2 } else { 4 };
+    let mut items = Vec::new();
+    for infe in parse_boxes(data, entries_at, iinf.end)? {
+        if &infe.kind == b"infe" {
+            items.push(parse_infe(data, &infe)?);
+        }
+    }
+    Ok(items)
+}
+
+fn parse_infe(data: &[u8], infe: &BoxRef) -> Result<Item> {
+    let mut r = Reader::new(data, infe.body, infe.end);
+    let version = r.u8()?;
+    r.skip(3)?;
+    let id = if version < 3 { r.u16()? as u32 } else { r.u32()? };
+    r.skip(2)?; // item_protection_index
+    let mut item_type = [0; 4];
+    let content_type;
+    if version >= 2 {
+        item_type.copy_from_slice(r.bytes(4)?);
+        r.cstring()?; // item_name
+        content_type = if &item_type == b"mime" { r.cstring()? } else { String::new() };
+    } else {
+        // Version 0/1 entries have no type, only a name and a content type
+        r.cstring()?;
+        content_type = r.cstring()?;
+        if content_type == "application/rdf+xml" {
+            item_type = *b"mime";
+        }
+    }
+    Ok(Item { id, item_type, content_type })
+}
+
+/// Field sizes declared in the iloc header
+struct IlocLayout {
+    version: u8,
+    offset_size: u8,
+    length_size: u8,
+    base_offset_size: u8,
+    index_size: u8,
+}
+
+impl IlocLayout {
+    fn read(r: &mut Reader) -> Result<Self> {
+        let version = r.u8()?;
+        r.skip(3)?;
+        let sizes = r.u8()?;
+        let more = r.u8()?;
+        Ok(IlocLayout {
+            version,
+            offset_size: sizes >> 4,
+            length_size: sizes & 0x0F,
+            base_offset_size: more >> 4,
+            index_size: if version == 1 || version == 2 { more & 0x0F } else { 0 },
+        })
+    }
+
+    /// Read one item entry, returning its location and the raw byte range it occupies
+    fn read_entry(&self, r: &mut Reader) -> Result<(Location, std::ops::Range<usize>)> {
+        let start = r.pos;
+        let id = if self.version < 2 { r.u16()? as u32 } else { r.u32()? };
+        let construction_method = if self.version == 1 || self.version == 2 { r.u16()? & 0x0F } else { 0 };
+        r.skip(2)?; // data_reference_index
+        let base_offset = r.uint(self.base_offset_size)?;
+        let extent_count = r.u16()?;
+        let mut extents = Vec::with_capacity(extent_count as usize);
+        for _ in 0..extent_count {
+            r.uint(self.index_size)?;
+            let offset = r.uint(self.offset_size)?;
+            let length = r.uint(self.length_size)?;
+            extents.push((base_offset.saturating_add(offset), length));
+        }
+        Ok((Location { id, construction_method, extents }, start..r.pos))
+    }
+}
+
+fn parse_iloc(data: &[u8], iloc: &BoxRef) -> Result<Vec<Location>> {
+    let mut r = Reader::new(data, iloc.body, iloc.end);
+    let layout = IlocLayout::read(&mut r)?;
+    let count = if layout.version < 2 { r.u16()? as u32 } else { r.u32()? };
+    (0..count).map(|_| layout.read_entry(&mut r).map(|(location, _)| location)).collect()
+}
+
+fn rebuild_iloc(data: &[u8], iloc: &BoxRef, removed: &[u32]) -> Result<Vec<u8>> {
+    let mut r = Reader::new(data, iloc.body, iloc.end);
+    let layout = IlocLayout::read(&mut r)?;
+    let header = data[iloc.body..r.pos].to_vec();
+    let count = if layout.version < 2 { r.u16()? as u32 } else { r.u32()? };
+    let mut kept = Vec::new();
+    let mut kept_count = 0u32;
+    for _ in 0..count {
+        let (location, range) = layout.read_entry(&mut r)?;
+        if !removed.contains(&location.id) {
+            kept.extend_from_slice(&data[range]);
+            kept_count += 1;
+        }
+    }
+    let mut body = header;
+    if layout.version < 2 {
+        body.extend_from_slice(&(kept_count as u16).to_be_bytes());
+    } else {
+        body.extend_from_slice(&kept_count.to_be_bytes());
+    }
+    body.extend_from_slice(&kept);
+    body.extend_from_slice(&data[r.pos..iloc.end]);
+    Ok(write_box(b"iloc", &body))
+}
+
+fn rebuild_iinf(data: &[u8], iinf: &BoxRef, removed: &[u32]) -> Result<Vec<u8>> {
+    let header = Reader::new(data, iinf.body, iinf.end).bytes(4)?;
+    let version = header[0];
+    let entries_at = iinf.body + 4 + if version == 0 { 2 } else { 4 };
+    let mut entries = Vec::new();
+    let mut count = 0u32;
+    for infe in parse_boxes(data, entries_at, iinf.end)? {
+        if &infe.kind == b"infe" && removed.contains(&parse_infe(data, &infe)?.id) {
+            continue;
+        }
+        entries.extend_from_slice(&data[infe.start..infe.end]);
+        count += 1;
+    }
+    let mut body = header.to_vec();
+    if version == 0 {
+        body.extend_from_slice(&(count as u16).to_be_bytes());
+    } else {
+        body.extend_from_slice(&count.to_be_bytes());
+    }
+    body.extend_from_slice(&entries);
+    Ok(write_box(b"iinf", &body))
+}
+
+fn rebuild_iref(data: &[u8], iref: &BoxRef, removed: &[u32]) -> Result<Vec<u8>> {
+    let header = Reader::new(data, iref.body, iref.end).bytes(4)?;
+    let wide = header[0] != 0;
+    let mut body = header.to_vec();
+    for reference in parse_boxes(data, iref.body + 4, iref.end)? {
+        let mut r = Reader::new(data, reference.body, reference.end);
+        let read_id = |r: &mut Reader| -> Result<u32> { if wide { r.u32() } else { r.u16().map(u32::from) } };
+        let from = read_id(&mut r)?;
+        let count = r.u16()?;
+        let mut to = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            to.push(read_id(&mut r)?);
+        }
+        to.retain(|id| !removed.contains(id));
+        if removed.contains(&from) || to.is_empty() {
+            continue;
+        }
+        let write_id = |out: &mut Vec<u8>, id: u32| {
+            if wide {
+                out.extend_from_slice(&id.to_be_bytes());
+            } else {
+                out.extend_from_slice(&(id as u16).to_be_bytes());
+            }
+        };
+        let mut ref_body = Vec::new();
+        write_id(&mut ref_body, from);
+        ref_body.extend_from_slice(&(to.len() as u16).to_be_bytes());
+        for id in to {
+            write_id(&mut ref_body, id);
+        }
+        body.extend_from_slice(&write_box(&reference.kind, &ref_body));
+    }
+    Ok(write_box(b"iref", &body))
+}
+
+/// Rebuild `iprp` without the associations of removed items, swapping the ICC `colr`
+/// property for its replacement if there is one
+fn rebuild_iprp(data: &[u8], iprp: &BoxRef, removed: &[u32], colr: Option<&(BoxRef, Vec<u8>)>) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    for child in parse_boxes(data, iprp.body, iprp.end)? {
+        match (&child.kind, colr) {
+            (b"ipma", _) => body.extend_from_slice(&rebuild_ipma(data, &child, removed)?),
+            (b"ipco", Some((colr, replacement))) if child.body <= colr.start && colr.end <= child.end => {
+                let ipco = [&data[child.body..colr.start], replacement, &data[colr.end..child.end]].concat();
+                body.extend_from_slice(&write_box(b"ipco", &ipco));
+            }
+            _ => body.extend_from_slice(&data[child.start..child.end]),
+        }
+    }
+    Ok(write_box(b"iprp", &body))
+}
+
+fn rebuild_ipma(data: &[u8], ipma: &BoxRef, removed: &[u32]) -> Result<Vec<u8>> {
+    let mut r = Reader::new(data, ipma.body, ipma.end);
+    let version = r.u8()?;
+    let flags = r.bytes(3)?;
+    let association_size = if flags[2] & 1 == 1 { 2 } else { 1 };
+    let count = r.u32()?;
+    let mut kept = Vec::new();
+    let mut kept_count = 0u32;
+    for _ in 0..count {
+        let start = r.pos;
+        let id = if version < 1 { r.u16()? as u32 } else { r.u32()? };
+        let associations = r.u8()? as usize;
+        r.skip(associations * association_size)?;
+        if !removed.contains(&id) {
+            kept.extend_from_slice(&data[start..r.pos]);
+            kept_count += 1;
+        }
+    }
+    let mut body = data[ipma.body..ipma.body + 4].to_vec();
+    body.extend_from_slice(&kept_count.to_be_bytes());
+    body.extend_from_slice(&kept);
+    Ok(write_box(b"ipma", &body))
+}
+
+fn parse_boxes(data: &[u8], start: usize, end: usize) -> Result<Vec<BoxRef>> {
+    let (boxes, stop) = parse_boxes_lenient(data, start, end);
+    if stop != end {
+        bail!("Invalid box at offset {}", stop);
+    }
+    Ok(boxes)
+}
+
+/// Parse consecutive boxes, stopping at the first one that does not fit.
+/// Returns the boxes and the offset where parsing stopped.
+fn parse_boxes_lenient(data: &[u8], mut pos: usize, end: usize) -> (Vec<BoxRef>, usize) {
+    let mut boxes = Vec::new();
+    while pos + 8 <= end {
+        let kind = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+        let (header, size) = match be32(data, pos) {
+            1 if pos + 16 <= end => (16, u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap())),
+            1 => break,
+            0 => (8, (end - pos) as u64),
+            size => (8, size as u64),
+        };
+        let box_end = usize::try_from(size)
+            .ok()
+            .filter(|&size| size >= header)
+            .and_then(|size| pos.checked_add(size))
+            .filter(|&box_end| box_end <= end);
+        let Some(box_end) = box_end else {
+            break;
+        };
+        boxes.push(BoxRef { kind, start: pos, body: pos + header, end: box_end });
+        pos = box_end;
+    }
+    (boxes, pos)
+}
+
+fn write_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 8);
+    out.extend_from_slice(&((body.len() + 8) as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+}
+
+fn be32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+/// Bounds-checked big-endian reader over a box payload
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize, end: usize) -> Self {
+        Reader { data, pos, end }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.pos + n > self.end {
+            bail!("Truncated box at offset {}", self.pos);
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Unsigned integer of 0 to 8 bytes
+    fn uint(&mut self, size: u8) -> Result<u64> {
+        Ok(self.bytes(size as usize)?.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+    }
+
+    fn cstring(&mut self) -> Result<String> {
+        let rest = &self.data[self.pos..self.end];
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        let s = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.pos = (self.pos + len + 1).min(self.end);
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PrivacyLevel;
+
+    const IMAGE: &[u8] = b"coded image data";
+    const THUMBNAIL: &[u8] = b"coded thumbnail";
+
+    fn full_box(kind: &[u8; 4], version: u8, body: &[u8]) -> Vec<u8> {
+        write_box(kind, &[&[version, 0, 0, 0], body].concat())
+    }
+
+    fn infe(id: u16, item_type: &[u8; 4], content_type: &str) -> Vec<u8> {
+        let content_type = if content_type.is_empty() { Vec::new() } else { [content_type.as_bytes(), b"\0"].concat() };
+        full_box(b"infe", 2, &[&id.to_be_bytes()[..], &[0, 0], item_type, b"\0", &content_type].concat())
+    }
+
+    fn reference(kind: &[u8; 4], from: u16, to: u16) -> Vec<u8> {
+        write_box(kind, &[from.to_be_bytes(), 1u16.to_be_bytes(), to.to_be_bytes()].concat())
+    }
+
+    /// A file with an image item (1), an Exif item (2) and a thumbnail (4) in `mdat`, and an
+    /// XMP item (3) in `idat`. `patch` can replace any child of `meta`.
+    fn sample(brand: &[u8; 4], patch: impl Fn(&[u8; 4], Vec<u8>) -> Vec<u8>) -> Vec<u8> {
+        let coding: &[u8; 4] = if brand == b"avif" { b"av01" } else { b"hvc1" };
+        let exif = [&[0, 0, 0, 0], &super::super::jpeg::tests::sample_exif()[6..]].concat();
+        let ftyp = write_box(b"ftyp", &[brand.as_slice(), &[0; 4], b"mif1", brand].concat());
+        let meta = |mdat: u32| {
+            // (id, construction method, offset, length)
+            let locations = [
+                (1u16, 0u16, mdat, IMAGE.len()),
+                (2, 0, mdat + IMAGE.len() as u32, exif.len()),
+                (3, 1, 0, xmp::tests::PACKET.len()),
+                (4, 0, mdat + (IMAGE.len() + exif.len()) as u32, THUMBNAIL.len()),
+            ];
+            let mut iloc = vec![0x44, 0x00];
+            iloc.extend_from_slice(&(locations.len() as u16).to_be_bytes());
+            for (id, method, offset, length) in locations {
+                iloc.extend_from_slice(&[id.to_be_bytes(), method.to_be_bytes(), [0, 0], 1u16.to_be_bytes()].concat());
+                iloc.extend_from_slice(&[offset.to_be_bytes(), (length as u32).to_be_bytes()].concat());
+            }
+            let iinf = [
+                &4u16.to_be_bytes()[..],
+                &infe(1, coding, ""),
+                &infe(2, b"Exif", ""),
+                &infe(3, b"mime", "application/rdf+xml"),
+                &infe(4, coding, ""),
+            ]
+            .concat();
+            let iref = [reference(b"cdsc", 2, 1), reference(b"cdsc", 3, 1), reference(b"thmb", 4, 1)].concat();
+            let ipco = write_box(b"ipco", &full_box(b"ispe", 0, &[8u32.to_be_bytes(), 8u32.to_be_bytes()].concat()));
+            let ipma = full_box(b"ipma", 0, &[&2u32.to_be_bytes()[..], &[0, 1, 1, 0x81], &[0, 4, 1, 0x81]].concat());
+            let children = [
+                (b"hdlr", full_box(b"hdlr", 0, &[&[0; 4][..], b"pict", &[0; 13]].concat())),
+                (b"pitm", full_box(b"pitm", 0, &1u16.to_be_bytes())),
+                (b"iinf", full_box(b"iinf", 0, &iinf)),
+                (b"iref", full_box(b"iref", 0, &iref)),
+                (b"iprp", write_box(b"iprp", &[ipco, ipma].concat())),
+                (b"iloc", full_box(b"iloc", 1, &iloc)),
+                (b"idat", write_box(b"idat", xmp::tests::PACKET)),
+            ];
+            let children: Vec<u8> = children.into_iter().flat_map(|(kind, bytes)| patch(kind, bytes)).collect();
+            full_box(b"meta", 0, &children)
+        };
+        let mdat_at = (ftyp.len() + meta(0).len() + 8) as u32;
+        let mdat = write_box(b"mdat", &[IMAGE, &exif, THUMBNAIL].concat());
+        [ftyp, meta(mdat_at), mdat].concat()
+    }
+
+    fn ids(heif: &Heif) -> Vec<u32> {
+        heif.items.iter().map(|i| i.id).collect()
+    }
+
+    #[test]
+    fn clean_keeps_the_image_and_every_offset() {
+        for brand in [b"heic", b"avif"] {
+            let original = sample(brand, |_, bytes| bytes);
+            let before = Heif::parse(&original).unwrap();
+            assert_eq!(ids(&before), [1, 2, 3, 4]);
+            assert_eq!(before.thumbnail_ids(&original).unwrap(), [4]);
+
+            // Only the orientation is left of the Exif and XMP items
+            let policy = RemovalPolicy::new(None, &[], &[]).unwrap();
+            let cleaned = clean(&original, &policy).unwrap();
+            assert_eq!(cleaned.len(), original.len());
+            let after = Heif::parse(&cleaned).unwrap();
+            assert_eq!(ids(&after), [1, 2, 3]);
+            assert_eq!(after.item_data(&cleaned, 1).unwrap(), IMAGE);
+            assert_eq!(exif_tags(&cleaned), [exif::Tag::Orientation]);
+            assert_eq!(after.item_data(&cleaned, 2).unwrap().len(), after.item_data(&original, 2).unwrap().len());
+            let packet = after.item_data(&cleaned, 3).unwrap();
+            assert_eq!(packet.len(), xmp::tests::PACKET.len());
+            let packet = String::from_utf8(packet).unwrap();
+            assert!(packet.contains("tiff:Orientation") && !packet.contains("dc:creator"));
+            assert!(packet.trim_end().ends_with("<?xpacket end=\"w\"?>"));
+            assert!(after.thumbnail_ids(&cleaned).unwrap().is_empty());
+            assert!(after.item_dimensions(&cleaned, 4).is_none() && after.item_dimensions(&cleaned, 1).is_some());
+
+            // The thumbnail payload is zero-filled in place, and a free box takes the room
+            // the meta box lost
+            let (boxes, _) = parse_boxes_lenient(&cleaned, 0, cleaned.len());
+            let kinds: Vec<&[u8; 4]> = boxes.iter().map(|b| &b.kind).collect();
+            assert_eq!(kinds, [b"ftyp", b"meta", b"free", b"mdat"]);
+            let mdat = boxes[3];
+            assert_eq!(mdat.start, parse_boxes_lenient(&original, 0, original.len()).0[2].start);
+            assert!(cleaned[mdat.end - THUMBNAIL.len()..mdat.end].iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn clean_honors_the_policy_for_xmp() {
+        let original = sample(b"heic", |_, bytes| bytes);
+        let strip = RemovalPolicy::new(None, &[], &["Nothing".to_string()]).unwrap();
+        let cleaned = clean(&original, &strip).unwrap();
+        let after = Heif::parse(&cleaned).unwrap();
+        assert_eq!(ids(&after), [1, 2, 3]);
+        assert_eq!(after.item_data(&cleaned, 3).unwrap(), xmp::tests::PACKET);
+
+        // The idat payload of a removed XMP item is zero-filled too
+        let all = RemovalPolicy::new(Some(PrivacyLevel::All), &[], &[]).unwrap();
+        let cleaned = clean(&original, &all).unwrap();
+        let after = Heif::parse(&cleaned).unwrap();
+        assert_eq!(ids(&after), [1]);
+        let idat = after.child(&cleaned, b"idat").unwrap();
+        assert!(cleaned[idat.body..idat.end].iter().all(|&b| b == 0));
+        assert!(containers(&cleaned).unwrap().is_empty());
+    }
+
+    fn exif_tags(data: &[u8]) -> Vec<exif::Tag> {
+        let tiff = exif_tiff(data).unwrap();
+        let exif_data = exif::Reader::new().read_raw(tiff).unwrap();
+        let mut tags: Vec<exif::Tag> = exif_data.fields().map(|f| f.tag).collect();
+        tags.sort_by_key(|t| t.number());
+        tags
+    }
+
+    #[test]
+    fn clean_honors_the_policy_for_exif() {
+        let original = sample(b"heic", |_, bytes| bytes);
+        let red = RemovalPolicy::new(Some(PrivacyLevel::Red), &[], &[]).unwrap();
+        let cleaned = clean(&original, &red).unwrap();
+        assert_eq!(ids(&Heif::parse(&cleaned).unwrap()), [1, 2, 3]);
+        assert_eq!(exif_tags(&cleaned), [exif::Tag::Make, exif::Tag::Orientation]);
+
+        let strip_gps = RemovalPolicy::new(None, &[], &["GPS*".to_string()]).unwrap();
+        let cleaned = clean(&original, &strip_gps).unwrap();
+        assert!(!exif_tags(&cleaned).contains(&exif::Tag::GPSLatitudeRef));
+        assert!(exif_tags(&cleaned).contains(&exif::Tag::Make));
+
+        let keep_gps = RemovalPolicy::new(None, &["GPS*".to_string()], &[]).unwrap();
+        let cleaned = clean(&original, &keep_gps).unwrap();
+        assert_eq!(exif_tags(&cleaned), [exif::Tag::GPSLatitudeRef]);
+        let mdat = parse_boxes_lenient(&cleaned, 0, cleaned.len()).0[3];
+        assert_eq!(&cleaned[mdat.body..mdat.body + IMAGE.len()], IMAGE);
+
+        let all = RemovalPolicy::new(Some(PrivacyLevel::All), &[], &[]).unwrap();
+        let cleaned = clean(&original, &all).unwrap();
+        assert_eq!(ids(&Heif::parse(&cleaned).unwrap()), [1]);
+        assert!(exif_tiff(&cleaned).is_none());
+    }
+
+    #[test]
+    fn truncated_boxes_are_errors() {
+        let policy = RemovalPolicy::new(None, &[], &[]).unwrap();
+        type Truncate = fn(Vec<u8>) -> Vec<u8>;
+        let truncations: [(&[u8; 4], Truncate); 4] = [
+            (b"iref", |_| write_box(b"iref", &[])),
+            (b"iref", |_| write_box(b"iref", &[0, 0])),
+            (b"iinf", |_| write_box(b"iinf", &[0])),
+            (b"iloc", |bytes| write_box(b"iloc", &bytes[8..bytes.len() - 3])),
+        ];
+        for (truncated, truncate) in truncations {
+            let data = sample(b"heic", |kind, bytes| if kind == truncated { truncate(bytes) } else { bytes });
+            assert!(clean(&data, &policy).is_err());
+            assert!(thumbnails(&data).is_empty());
+        }
+        let data = sample(b"heic", |_, bytes| bytes);
+        for len in 0..data.len() {
+            let _ = clean(&data[..len], &policy);
+            let _ = containers(&data[..len]);
+        }
+    }
+}
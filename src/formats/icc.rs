@@ -0,0 +1,302 @@
+use std::ops::Range;
+use anyhow::{bail, Result};
+use super::Container;
+use crate::entry::{MetadataEntry, Value};
+use crate::policy::IccMode;
+
+/// Prefix of the keys under which profile fields are reported (`Icc.ProfileDescription`)
+pub const ICC_PREFIX: &str = "Icc.";
+
+/// Description of the profile written by `clean --icc srgb`
+pub const SRGB_DESCRIPTION: &str = "sRGB IEC61966-2.1";
+
+const HEADER_LEN: usize = 128;
+
+/// D50, the illuminant of the profile connection space
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// A parsed ICC profile: the 128-byte header and the tag table
+pub struct Profile<'a> {
+    data: &'a [u8],
+    tags: Vec<([u8; 4], Range<usize>)>,
+}
+
+impl<'a> Profile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if data.len() < HEADER_LEN + 4 || &data[36..40] != b"acsp" {
+            bail!("Not an ICC profile");
+        }
+        let count = be32(data, HEADER_LEN) as usize;
+        let mut tags = Vec::new();
+        for i in 0..count {
+            let at = HEADER_LEN + 4 + i * 12;
+            if at + 12 > data.len() {
+                bail!("Truncated ICC tag table");
+            }
+            let signature = [data[at], data[at + 1], data[at + 2], data[at + 3]];
+            let offset = be32(data, at + 4) as usize;
+            let size = be32(data, at + 8) as usize;
+            // Skip tags pointing outside the profile rather than rejecting it
+            if offset.checked_add(size).is_some_and(|end| end <= data.len()) {
+                tags.push((signature, offset..offset + size));
+            }
+        }
+        Ok(Profile { data, tags })
+    }
+
+    /// Data color space signature (`RGB `, `GRAY`, `CMYK`, ...)
+    pub fn color_space(&self) -> [u8; 4] {
+        signature(self.data, 16)
+    }
+
+    /// Version as `major.minor.bugfix`
+    pub fn version(&self) -> String {
+        format!("{}.{}.{}", self.data[8], self.data[9] >> 4, self.data[9] & 0x0F)
+    }
+
+    /// Creation date and time, as in EXIF (`2023:04:01 12:00:00`)
+    pub fn created(&self) -> Option<String> {
+        let field = |i: usize| u16::from_be_bytes([self.data[24 + i * 2], self.data[25 + i * 2]]);
+        let [year, month, day, hour, minute, second] = [0, 1, 2, 3, 4, 5].map(field);
+        (year != 0).then(|| format!("{:04}:{:02}:{:02} {:02}:{:02}:{:02}", year, month, day, hour, minute, second))
+    }
+
+    /// Text of a `desc`, `mluc` or `text` tag
+    pub fn text(&self, tag: &[u8; 4]) -> Option<String> {
+        let (_, range) = self.tags.iter().find(|(signature, _)| signature == tag)?;
+        let data = &self.data[range.clone()];
+        let text = match data.get(..4)? {
+            // v2 textDescriptionType: ASCII count (including the NUL), then the string
+            b"desc" => {
+                let count = be32(data.get(..12)?, 8) as usize;
+                ascii(data.get(12..12 + count)?)
+            }
+            // v4 multiLocalizedUnicodeType: the first record, in UTF-16BE
+            b"mluc" => {
+                let record = data.get(16..28)?;
+                let (len, offset) = (be32(record, 4) as usize, be32(record, 8) as usize);
+                let units: Vec<u16> = data
+                    .get(offset..offset.checked_add(len)?)?
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units).trim_end_matches('\0').to_string()
+            }
+            b"text" => ascii(data.get(8..)?),
+            _ => return None,
+        };
+        Some(text).filter(|t| !t.trim().is_empty())
+    }
+
+    /// Header fields (group `Header`) and text tags (group `Tags`), keyed like exiftool's
+    /// ICC_Profile group (`Icc.ProfileDescription`)
+    pub fn entries(&self) -> Vec<MetadataEntry> {
+        let mut header = vec![
+            ("ProfileVersion", Some(self.version())),
+            ("ProfileClass", Some(class_name(signature(self.data, 12)))),
+            ("ColorSpaceData", signature_name(self.data, 16)),
+            ("ProfileConnectionSpace", signature_name(self.data, 20)),
+            ("ProfileDateTime", self.created()),
+        ];
+        for (name, at) in [("ProfileCMMType", 4), ("PrimaryPlatform", 40), ("DeviceManufacturer", 48), ("DeviceModel", 52), ("ProfileCreator", 80)] {
+            header.push((name, signature_name(self.data, at)));
+        }
+        let mut entries: Vec<MetadataEntry> = header
+            .into_iter()
+            .filter_map(|(name, value)| Some(entry("Header", name, value?)))
+            .collect();
+        for (name, tag) in [
+            ("ProfileDescription", b"desc"),
+            ("ProfileCopyright", b"cprt"),
+            ("DeviceMfgDesc", b"dmnd"),
+            ("DeviceModelDesc", b"dmdd"),
+        ] {
+            if let Some(text) = self.text(tag) {
+                entries.push(entry("Tags", name, text).with_tag_id(u32::from_be_bytes(*tag)));
+            }
+        }
+        entries
+    }
+}
+
+fn entry(group: &str, name: &str, value: String) -> MetadataEntry {
+    MetadataEntry::new(Some(Container::Icc), group, name, format!("{}{}", ICC_PREFIX, name), Value::Text(value))
+}
+
+/// What `clean` does with an embedded profile
+pub enum Rewrite {
+    Keep,
+    Strip,
+    /// Write this profile instead
+    Replace(Vec<u8>),
+}
+
+/// Decide what becomes of `profile`. `srgb` only replaces RGB profiles, since a grayscale or
+/// CMYK image cannot be described by an RGB profile; those are kept, like unreadable profiles.
+pub fn rewrite(profile: &[u8], mode: IccMode) -> Rewrite {
+    match mode {
+        IccMode::Keep => Rewrite::Keep,
+        IccMode::Strip => Rewrite::Strip,
+        IccMode::Srgb => match Profile::parse(profile) {
+            Ok(parsed) if &parsed.color_space() == b"RGB " && parsed.text(b"desc").as_deref() != Some(SRGB_DESCRIPTION) => {
+                Rewrite::Replace(srgb())
+            }
+            _ => Rewrite::Keep,
+        },
+    }
+}
+
+/// A minimal ICC v4 display profile for sRGB: D65 primaries adapted to D50 (Bradford),
+/// the sRGB transfer curve as a parametric curve, and no device or creation information
+pub fn srgb() -> Vec<u8> {
+    let curve = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045];
+    let chad = [
+        1.0478112, 0.0228866, -0.0501270,
+        0.0295424, 0.9904844, -0.0170491,
+        -0.0092345, 0.0150436, 0.7521316,
+    ];
+    let trc = [b"para".as_slice(), &[0; 4], &[0, 3, 0, 0], &fixed_all(&curve)].concat();
+    let tags: [(&[u8; 4], Vec<u8>); 8] = [
+        (b"desc", mluc(SRGB_DESCRIPTION)),
+        (b"cprt", mluc("No copyright, use freely")),
+        (b"wtpt", xyz(D50)),
+        (b"chad", [b"sf32".as_slice(), &[0; 4], &fixed_all(&chad)].concat()),
+        (b"rXYZ", xyz([0.4360747, 0.2225045, 0.0139322])),
+        (b"gXYZ", xyz([0.3850649, 0.7168786, 0.0971045])),
+        (b"bXYZ", xyz([0.1430804, 0.0606169, 0.7141733])),
+        (b"rTRC", trc),
+    ];
+    // gTRC and bTRC share the data of rTRC
+    let count = tags.len() + 2;
+    let mut table = Vec::new();
+    let mut body = Vec::new();
+    let mut offset = HEADER_LEN + 4 + count * 12;
+    for (signature, data) in &tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        body.extend_from_slice(data);
+        body.resize(body.len().next_multiple_of(4), 0);
+        offset = HEADER_LEN + 4 + count * 12 + body.len();
+    }
+    let shared = table[table.len() - 8..].to_vec();
+    for signature in [b"gTRC", b"bTRC"] {
+        table.extend_from_slice(signature);
+        table.extend_from_slice(&shared);
+    }
+
+    let size = (HEADER_LEN + 4 + table.len() + body.len()) as u32;
+    let mut out = Vec::with_capacity(size as usize);
+    out.extend_from_slice(&size.to_be_bytes());
+    out.extend_from_slice(&[0; 4]); // CMM
+    out.extend_from_slice(&[4, 0x30, 0, 0]); // version 4.3
+    out.extend_from_slice(b"mntrRGB XYZ ");
+    // Fixed creation date, so cleaned files don't say when they were cleaned
+    for field in [2000u16, 1, 1, 0, 0, 0] {
+        out.extend_from_slice(&field.to_be_bytes());
+    }
+    out.extend_from_slice(b"acsp");
+    out.extend_from_slice(&[0; 24]); // platform, flags, manufacturer, model, attributes
+    out.extend_from_slice(&[0; 4]); // perceptual intent
+    out.extend_from_slice(&fixed_all(&D50));
+    out.extend_from_slice(&[0; 4 + 16 + 28]); // creator, profile ID (not computed), reserved
+    out.extend_from_slice(&(count as u32).to_be_bytes());
+    out.extend_from_slice(&table);
+    out.extend_from_slice(&body);
+    out
+}
+
+/// multiLocalizedUnicodeType with a single en-US record
+fn mluc(text: &str) -> Vec<u8> {
+    let units: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+    let mut out = b"mluc".to_vec();
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&1u32.to_be_bytes());
+    out.extend_from_slice(&12u32.to_be_bytes());
+    out.extend_from_slice(b"enUS");
+    out.extend_from_slice(&(units.len() as u32).to_be_bytes());
+    out.extend_from_slice(&28u32.to_be_bytes());
+    out.extend_from_slice(&units);
+    out
+}
+
+fn xyz(values: [f64; 3]) -> Vec<u8> {
+    [b"XYZ ".as_slice(), &[0; 4], &fixed_all(&values)].concat()
+}
+
+/// Encode values as s15Fixed16Number
+fn fixed_all(values: &[f64]) -> Vec<u8> {
+    values.iter().flat_map(|v| ((v * 65536.0).round() as i32).to_be_bytes()).collect()
+}
+
+fn signature(data: &[u8], at: usize) -> [u8; 4] {
+    [data[at], data[at + 1], data[at + 2], data[at + 3]]
+}
+
+/// A header signature as text, `None` when unset
+fn signature_name(data: &[u8], at: usize) -> Option<String> {
+    let signature = signature(data, at);
+    if signature == [0; 4] {
+        return None;
+    }
+    if signature.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        Some(String::from_utf8_lossy(&signature).trim_end().to_string())
+    } else {
+        Some(format!("0x{:08x}", u32::from_be_bytes(signature)))
+    }
+}
+
+fn class_name(class: [u8; 4]) -> String {
+    let name = match &class {
+        b"scnr" => "Input Device Profile",
+        b"mntr" => "Display Device Profile",
+        b"prtr" => "Output Device Profile",
+        b"link" => "DeviceLink Profile",
+        b"spac" => "ColorSpace Conversion Profile",
+        b"abst" => "Abstract Profile",
+        b"nmcl" => "NamedColor Profile",
+        _ => return String::from_utf8_lossy(&class).trim_end().to_string(),
+    };
+    name.to_string()
+}
+
+fn ascii(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn be32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_profile_parses_back() {
+        let data = srgb();
+        let profile = Profile::parse(&data).unwrap();
+        assert_eq!(be32(&data, 0) as usize, data.len());
+        assert_eq!(&profile.color_space(), b"RGB ");
+        assert_eq!(profile.version(), "4.3.0");
+        assert_eq!(profile.text(b"desc").as_deref(), Some(SRGB_DESCRIPTION));
+        assert!(profile.tags.iter().all(|(_, range)| range.start % 4 == 0));
+        let curve = |tag: &[u8; 4]| profile.tags.iter().find(|(s, _)| s == tag).map(|(_, r)| r.clone());
+        assert!(curve(b"rTRC").is_some());
+        assert_eq!(curve(b"gTRC"), curve(b"rTRC"));
+        assert_eq!(curve(b"bTRC"), curve(b"rTRC"));
+    }
+
+    #[test]
+    fn srgb_mode_only_replaces_rgb_profiles() {
+        let mut gray = srgb();
+        gray[16..20].copy_from_slice(b"GRAY");
+        assert!(matches!(rewrite(&gray, IccMode::Srgb), Rewrite::Keep));
+        let mut device = srgb();
+        // Corrupting the description makes it a "device" profile for the check
+        device[HEADER_LEN + 4..HEADER_LEN + 8].copy_from_slice(b"dscX");
+        assert!(matches!(rewrite(&device, IccMode::Srgb), Rewrite::Replace(_)));
+        assert!(matches!(rewrite(&srgb(), IccMode::Srgb), Rewrite::Keep));
+    }
+}
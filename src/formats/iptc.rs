@@ -0,0 +1,127 @@
+use anyhow::{bail, Result};
+use super::tiff::{read_u16, read_u32};
+use crate::policy::RemovalPolicy;
+
+/// Signature of a Photoshop image resource
+const RESOURCE_SIGNATURE: &[u8] = b"8BIM";
+/// Image resource holding the IPTC-IIM records
+const RESOURCE_IPTC: u16 = 0x0404;
+/// Marker starting every IPTC-IIM dataset
+const TAG_MARKER: u8 = 0x1C;
+
+/// Names exiv2 gives to the datasets of the envelope record (1)
+const ENVELOPE: [(u8, &str); 14] = [
+    (0, "ModelVersion"), (5, "Destination"), (20, "FileFormat"), (22, "FileVersion"), (30, "ServiceId"),
+    (40, "EnvelopeNumber"), (50, "ProductId"), (60, "EnvelopePriority"), (70, "DateSent"), (80, "TimeSent"),
+    (90, "CharacterSet"), (100, "UNO"), (120, "ARMId"), (122, "ARMVersion"),
+];
+
+/// Names exiv2 gives to the datasets of the application record (2)
+const APPLICATION2: [(u8, &str); 50] = [
+    (0, "RecordVersion"), (3, "ObjectType"), (4, "ObjectAttribute"), (5, "ObjectName"), (7, "EditStatus"),
+    (8, "EditorialUpdate"), (10, "Urgency"), (12, "Subject"), (15, "Category"), (20, "SuppCategory"),
+    (22, "FixtureId"), (25, "Keywords"), (26, "LocationCode"), (27, "LocationName"), (30, "ReleaseDate"),
+    (35, "ReleaseTime"), (37, "ExpirationDate"), (38, "ExpirationTime"), (40, "SpecialInstructions"),
+    (42, "ActionAdvised"), (45, "ReferenceService"), (47, "ReferenceDate"), (50, "ReferenceNumber"),
+    (55, "DateCreated"), (60, "TimeCreated"), (62, "DigitizationDate"), (63, "DigitizationTime"),
+    (65, "Program"), (70, "ProgramVersion"), (75, "ObjectCycle"), (80, "Byline"), (85, "BylineTitle"),
+    (90, "City"), (92, "SubLocation"), (95, "ProvinceState"), (100, "CountryCode"), (101, "CountryName"),
+    (103, "TransmissionReference"), (105, "Headline"), (110, "Credit"), (115, "Source"), (116, "Copyright"),
+    (118, "Contact"), (120, "Caption"), (122, "Writer"), (125, "RasterizedCaption"), (130, "ImageType"),
+    (131, "ImageOrientation"), (135, "Language"), (150, "AudioType"),
+];
+
+/// Datasets describing the records themselves, kept as long as any other dataset is
+const STRUCTURAL: [(u8, u8); 3] = [(1, 0), (1, 90), (2, 0)];
+
+/// exiv2 key of a dataset, e.g. `Iptc.Application2.Byline`
+pub fn key(record: u8, dataset: u8) -> String {
+    let (record_name, names): (String, &[(u8, &str)]) = match record {
+        1 => ("Envelope".to_string(), &ENVELOPE),
+        2 => ("Application2".to_string(), &APPLICATION2),
+        _ => (format!("0x{:04x}", record), &[]),
+    };
+    match names.iter().find(|(number, _)| *number == dataset) {
+        Some((_, name)) => format!("Iptc.{}.{}", record_name, name),
+        None => format!("Iptc.{}.0x{:04x}", record_name, dataset),
+    }
+}
+
+/// Rebuild the Photoshop image resources of a JPEG APP13 segment (after the "Photoshop 3.0"
+/// header) with only the IPTC datasets the policy keeps. The other resources (thumbnails,
+/// print settings, the now outdated IPTC digest, ...) are dropped.
+/// Returns `None` when no dataset is left.
+pub fn filter_resources(resources: &[u8], policy: &RemovalPolicy) -> Result<Option<Vec<u8>>> {
+    let mut records = Vec::new();
+    let mut pos = 0;
+    // Some writers pad the segment with zeros after the last resource
+    while resources.get(pos..pos + 4) == Some(RESOURCE_SIGNATURE) {
+        let invalid = || anyhow::anyhow!("Invalid Photoshop image resource at offset {}", pos);
+        let id = read_u16(resources, pos + 4, false).ok_or_else(invalid)?;
+        // Pascal string name, padded to an even length
+        let name_len = *resources.get(pos + 6).ok_or_else(invalid)? as usize;
+        let size_at = pos + 6 + ((name_len + 2) & !1);
+        let size = read_u32(resources, size_at, false).ok_or_else(invalid)? as usize;
+        let data = resources.get(size_at + 4..size_at + 4 + size).ok_or_else(invalid)?;
+        if id == RESOURCE_IPTC {
+            records.extend_from_slice(data);
+        }
+        pos = size_at + 4 + size + (size & 1);
+    }
+    let Some(records) = filter_records(&records, policy)? else {
+        return Ok(None);
+    };
+    let mut out = Vec::with_capacity(records.len() + 13);
+    out.extend_from_slice(RESOURCE_SIGNATURE);
+    out.extend_from_slice(&RESOURCE_IPTC.to_be_bytes());
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(&(records.len() as u32).to_be_bytes());
+    out.extend_from_slice(&records);
+    if records.len() % 2 == 1 {
+        out.push(0);
+    }
+    Ok(Some(out))
+}
+
+/// Keep the IPTC-IIM datasets the policy does not remove, in their original order
+pub fn filter_records(data: &[u8], policy: &RemovalPolicy) -> Result<Option<Vec<u8>>> {
+    let mut datasets = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        if data[pos] != TAG_MARKER {
+            // Trailing padding
+            if data[pos..].iter().all(|&b| b == 0) {
+                break;
+            }
+            bail!("Invalid IPTC dataset at offset {}", pos);
+        }
+        let (Some(&record), Some(&dataset), Some(len)) = (data.get(pos + 1), data.get(pos + 2), read_u16(data, pos + 3, false))
+        else {
+            bail!("Truncated IPTC dataset at offset {}", pos);
+        };
+        // Extended datasets give the size of their length field instead
+        let (header, size) = if len & 0x8000 != 0 {
+            let count = (len & 0x7FFF) as usize;
+            let bytes = data.get(pos + 5..pos + 5 + count).filter(|b| b.len() <= 8);
+            let size = bytes.map(|b| b.iter().fold(0usize, |size, &b| size << 8 | b as usize));
+            (5 + count, size)
+        } else {
+            (5, Some(len as usize))
+        };
+        let end = size
+            .and_then(|size| (pos + header).checked_add(size))
+            .filter(|&end| end <= data.len())
+            .ok_or_else(|| anyhow::anyhow!("Truncated IPTC dataset at offset {}", pos))?;
+        datasets.push((record, dataset, &data[pos..end]));
+        pos = end;
+    }
+    let structural = |record, dataset| STRUCTURAL.contains(&(record, dataset));
+    let kept: Vec<_> = datasets
+        .iter()
+        .filter(|&&(record, dataset, _)| structural(record, dataset) || !policy.should_remove(&key(record, dataset)))
+        .collect();
+    if kept.iter().all(|&&(record, dataset, _)| structural(record, dataset)) {
+        return Ok(None);
+    }
+    Ok(Some(kept.into_iter().flat_map(|&(_, _, bytes)| bytes.to_vec()).collect()))
+}
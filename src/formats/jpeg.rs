@@ -0,0 +1,572 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use anyhow::{bail, Context, Result};
+use super::icc::{self, Rewrite};
+use super::tiff::{read_u16, read_u32};
+use super::{iptc, xmp, Container};
+use crate::entry::{MetadataEntry, Value};
+use crate::policy::RemovalPolicy;
+
+pub const SOI: [u8; 2] = [0xFF, 0xD8];
+
+pub const APP0: u8 = 0xE0;
+pub const APP1: u8 = 0xE1;
+pub const APP2: u8 = 0xE2;
+pub const APP13: u8 = 0xED;
+pub const APP14: u8 = 0xEE;
+pub const APP15: u8 = 0xEF;
+pub const COM: u8 = 0xFE;
+pub const SOS: u8 = 0xDA;
+pub const EOI: u8 = 0xD9;
+
+pub const JFIF_HEADER: &[u8] = b"JFIF\0";
+pub const EXIF_HEADER: &[u8] = b"Exif\0\0";
+pub const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+pub const XMP_EXTENSION_HEADER: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+pub const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+pub const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+pub const ADOBE_HEADER: &[u8] = b"Adobe";
+pub const MPF_HEADER: &[u8] = b"MPF\0";
+
+/// Largest ICC chunk that fits in an APP2 segment, after the header, sequence number and count
+const ICC_CHUNK_LEN: usize = 65535 - 2 - ICC_HEADER.len() - 2;
+
+/// MP Entry tag of the MPF index IFD
+const TAG_MP_ENTRY: u16 = 0xB002;
+
+/// Length of the GUID that links the chunks of an extended XMP packet
+const XMP_GUID_LEN: usize = 32;
+
+/// A marker segment of a JPEG file
+pub struct Segment<'a> {
+    pub marker: u8,
+    /// Offset of the segment in the file
+    pub offset: usize,
+    /// Raw bytes of the segment, starting at the 0xFF marker prefix.
+    /// For SOS this also covers the entropy-coded data that follows it.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    /// Segment payload after the marker and length field (empty for standalone markers)
+    pub fn payload(&self) -> &'a [u8] {
+        if has_length(self.marker) && self.bytes.len() >= 4 {
+            let len = u16::from_be_bytes([self.bytes[2], self.bytes[3]]) as usize;
+            &self.bytes[4..(2 + len).min(self.bytes.len())]
+        } else {
+            &[]
+        }
+    }
+
+    /// Metadata container carried by this segment, if any
+    pub fn container(&self) -> Option<Container> {
+        let payload = self.payload();
+        match self.marker {
+            APP1 if payload.starts_with(EXIF_HEADER) => Some(Container::Exif),
+            APP1 if payload.starts_with(XMP_HEADER) || payload.starts_with(XMP_EXTENSION_HEADER) => Some(Container::Xmp),
+            APP2 if payload.starts_with(ICC_HEADER) => Some(Container::Icc),
+            APP13 if payload.starts_with(PHOTOSHOP_HEADER) => Some(Container::Iptc),
+            COM => Some(Container::Comment),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed JPEG file: its segments up to EOI and the offset where the image ends
+pub struct Jpeg<'a> {
+    pub segments: Vec<Segment<'a>>,
+    /// Offset just past the EOI marker; anything after it is trailing data
+    pub end: usize,
+}
+
+impl<'a> Jpeg<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(&SOI) {
+            bail!("Not a JPEG file");
+        }
+        let mut segments = vec![Segment { marker: SOI[1], offset: 0, bytes: &data[..2] }];
+        let mut pos = 2;
+        loop {
+            if pos >= data.len() {
+                // Missing EOI: treat the rest of the file as part of the image
+                return Ok(Jpeg { segments, end: data.len() });
+            }
+            if data[pos] != 0xFF {
+                bail!("Expected JPEG marker at offset {}", pos);
+            }
+            // Skip fill bytes
+            while pos + 1 < data.len() && data[pos + 1] == 0xFF {
+                pos += 1;
+            }
+            if pos + 1 >= data.len() {
+                bail!("Truncated JPEG marker at offset {}", pos);
+            }
+            let marker = data[pos + 1];
+            let start = pos;
+            if !has_length(marker) {
+                pos += 2;
+                segments.push(Segment { marker, offset: start, bytes: &data[start..pos] });
+                if marker == EOI {
+                    return Ok(Jpeg { segments, end: pos });
+                }
+                continue;
+            }
+            if pos + 4 > data.len() {
+                bail!("Truncated JPEG segment at offset {}", pos);
+            }
+            let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            if len < 2 || pos + 2 + len > data.len() {
+                bail!("Invalid JPEG segment length at offset {}", pos);
+            }
+            pos += 2 + len;
+            if marker == SOS {
+                pos = entropy_coded_end(data, pos);
+            }
+            segments.push(Segment { marker, offset: start, bytes: &data[start..pos] });
+        }
+    }
+
+    /// Bytes after the EOI marker
+    pub fn trailer(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.end..]
+    }
+
+    /// Raw TIFF block of the EXIF segment, if any
+    pub fn exif_tiff(&self) -> Option<&'a [u8]> {
+        self.segments
+            .iter()
+            .find(|s| s.container() == Some(Container::Exif))
+            .map(|s| &s.payload()[EXIF_HEADER.len()..])
+    }
+
+    /// ICC profile, reassembled from its APP2 segments in sequence order
+    pub fn icc_profile(&self) -> Option<Vec<u8>> {
+        let mut chunks: Vec<(u8, &[u8])> = self
+            .segments
+            .iter()
+            .filter(|s| s.container() == Some(Container::Icc))
+            .filter_map(|s| {
+                let payload = s.payload();
+                Some((*payload.get(ICC_HEADER.len())?, payload.get(ICC_HEADER.len() + 2..)?))
+            })
+            .collect();
+        if chunks.is_empty() {
+            return None;
+        }
+        chunks.sort_by_key(|&(sequence, _)| sequence);
+        Some(chunks.into_iter().flat_map(|(_, chunk)| chunk.to_vec()).collect())
+    }
+
+    /// Byte ranges of the images indexed by an MPF segment (previews, depth maps, ...),
+    /// not counting the primary image. They are stored after EOI.
+    pub fn mpf_images(&self, data: &[u8]) -> Vec<Range<usize>> {
+        let Some(segment) = self.segments.iter().find(|s| s.marker == APP2 && s.payload().starts_with(MPF_HEADER))
+        else {
+            return Vec::new();
+        };
+        // MPF offsets are relative to the TIFF header that follows "MPF\0"
+        let base = segment.offset + 4 + MPF_HEADER.len();
+        mp_entries(&segment.payload()[MPF_HEADER.len()..])
+            .into_iter()
+            .filter(|&(_, offset)| offset != 0)
+            .filter_map(|(size, offset)| {
+                let start = base.checked_add(offset)?;
+                let end = start.checked_add(size)?;
+                (end <= data.len()).then_some(start..end)
+            })
+            .collect()
+    }
+
+    /// JFIF thumbnail as width, height and raw RGB pixels
+    pub fn jfif_thumbnail(&self) -> Option<(u32, u32, &'a [u8])> {
+        let payload = self
+            .segments
+            .iter()
+            .find(|s| s.marker == APP0 && s.payload().starts_with(JFIF_HEADER))?
+            .payload();
+        let (width, height) = (*payload.get(12)? as usize, *payload.get(13)? as usize);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let pixels = payload.get(14..14 + 3 * width * height)?;
+        Some((width as u32, height as u32, pixels))
+    }
+
+    /// Image dimensions from the frame header
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        let frame = self
+            .segments
+            .iter()
+            .find(|s| matches!(s.marker, 0xC0..=0xCF) && !matches!(s.marker, 0xC4 | 0xC8 | 0xCC))?
+            .payload();
+        let height = u16::from_be_bytes([*frame.get(1)?, *frame.get(2)?]);
+        let width = u16::from_be_bytes([*frame.get(3)?, *frame.get(4)?]);
+        Some((width as u32, height as u32))
+    }
+}
+
+/// (size, offset) of each MP Entry in an MPF index IFD
+fn mp_entries(tiff: &[u8]) -> Vec<(usize, usize)> {
+    let le = match tiff.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return Vec::new(),
+    };
+    let Some(ifd) = read_u32(tiff, 4, le) else {
+        return Vec::new();
+    };
+    let ifd = ifd as usize;
+    let count = read_u16(tiff, ifd, le).unwrap_or(0) as usize;
+    let Some(entry) = (0..count).map(|i| ifd + 2 + i * 12).find(|&at| read_u16(tiff, at, le) == Some(TAG_MP_ENTRY))
+    else {
+        return Vec::new();
+    };
+    let (Some(len), Some(offset)) = (read_u32(tiff, entry + 4, le), read_u32(tiff, entry + 8, le)) else {
+        return Vec::new();
+    };
+    (0..len as usize / 16)
+        .filter_map(|i| {
+            let at = offset as usize + i * 16;
+            Some((read_u32(tiff, at + 4, le)? as usize, read_u32(tiff, at + 8, le)? as usize))
+        })
+        .collect()
+}
+
+/// Key of the `index`-th (from 1) comment segment: `Jpeg.Comment`, then `Jpeg.Comment[2]`, ...
+fn comment_key(index: usize) -> String {
+    match index {
+        1 => "Jpeg.Comment".to_string(),
+        _ => format!("Jpeg.Comment[{}]", index),
+    }
+}
+
+/// Comment (COM) segments, as typed entries
+pub fn comment_entries(data: &[u8]) -> Result<Vec<MetadataEntry>> {
+    let jpeg = Jpeg::parse(data)?;
+    let comments = jpeg.segments.iter().filter(|s| s.marker == COM);
+    Ok(comments
+        .enumerate()
+        .map(|(i, segment)| {
+            let text = String::from_utf8_lossy(segment.payload()).trim_end_matches('\0').to_string();
+            MetadataEntry::new(Some(Container::Comment), "Comment", "Comment", comment_key(i + 1), Value::Text(text))
+        })
+        .collect())
+}
+
+/// GUIDs of the extended XMP packets to keep: the ones the policy removes nothing from.
+/// An extended packet (e.g. a depth map) is split across segments and named after its MD5,
+/// so it is kept or dropped as a whole rather than rewritten.
+fn kept_xmp_extensions<'a>(jpeg: &Jpeg<'a>, policy: &RemovalPolicy) -> Vec<&'a [u8]> {
+    let mut packets: HashMap<&[u8], Vec<u8>> = HashMap::new();
+    for segment in jpeg.segments.iter().filter(|s| s.marker == APP1) {
+        let Some(chunk) = segment.payload().strip_prefix(XMP_EXTENSION_HEADER) else {
+            continue;
+        };
+        let (Some(guid), Some(len), Some(offset), Some(data)) = (
+            chunk.get(..XMP_GUID_LEN),
+            read_u32(chunk, XMP_GUID_LEN, false),
+            read_u32(chunk, XMP_GUID_LEN + 4, false),
+            chunk.get(XMP_GUID_LEN + 8..),
+        ) else {
+            continue;
+        };
+        let packet = packets.entry(guid).or_insert_with(|| vec![0; len as usize]);
+        if let Some(target) = packet.get_mut(offset as usize..offset as usize + data.len()) {
+            target.copy_from_slice(data);
+        }
+    }
+    packets
+        .into_iter()
+        .filter(|(_, packet)| matches!(xmp::filter(packet, policy), Ok(Some(Cow::Borrowed(_)))))
+        .map(|(guid, _)| guid)
+        .collect()
+}
+
+/// Markers without a length field
+fn has_length(marker: u8) -> bool {
+    !matches!(marker, 0x01 | 0xD0..=0xD9)
+}
+
+/// Find the end of the entropy-coded data starting at `pos`: the next marker that is
+/// neither a stuffed 0xFF00 byte nor a restart marker
+fn entropy_coded_end(data: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < data.len() {
+        if data[pos] == 0xFF {
+            match data[pos + 1] {
+                0x00 | 0xD0..=0xD7 => pos += 2,
+                _ => return pos,
+            }
+        } else {
+            pos += 1;
+        }
+    }
+    data.len()
+}
+
+/// Detect the metadata containers in a JPEG file
+pub fn containers(data: &[u8]) -> Result<Vec<Container>> {
+    let jpeg = Jpeg::parse(data)?;
+    let mut found = Vec::new();
+    for segment in &jpeg.segments {
+        let payload = segment.payload();
+        if let Some(container) = segment.container() {
+            found.push(container);
+            if container == Container::Exif && super::exif_has_thumbnail(&payload[EXIF_HEADER.len()..]) {
+                found.push(Container::Thumbnail);
+            }
+        }
+        // JFIF thumbnail dimensions are at offsets 12/13, JFXX extensions always carry one
+        if segment.marker == APP0
+            && ((payload.starts_with(JFIF_HEADER) && payload.len() >= 14 && payload[12] > 0 && payload[13] > 0)
+                || payload.starts_with(b"JFXX\0"))
+        {
+            found.push(Container::Thumbnail);
+        }
+    }
+    if !jpeg.mpf_images(data).is_empty() {
+        found.push(Container::Thumbnail);
+    }
+    if !jpeg.trailer(data).is_empty() {
+        found.push(Container::Trailer);
+    }
+    Ok(found)
+}
+
+/// Rewrite a JPEG without the metadata selected by `policy`, without re-encoding.
+///
+/// Every segment needed for decoding (tables, frame headers, scans and their
+/// entropy-coded data) is copied byte-for-byte. EXIF is rebuilt with only the
+/// primary-image tags the policy keeps, dropping the IFD1 thumbnail. XMP and IPTC keep
+/// the properties and datasets the policy keeps, and comments are kept or removed one by one.
+/// Other application segments are removed as a whole, except the JFIF header (without its
+/// thumbnail), the Adobe color transform and the ICC profile, which is kept, stripped or
+/// replaced following the policy's ICC mode. Data after EOI is dropped unless the policy
+/// keeps it, and even then the preview images indexed by an MPF segment are cut out.
+pub fn clean(data: &[u8], policy: &RemovalPolicy) -> Result<Vec<u8>> {
+    let jpeg = Jpeg::parse(data)?;
+    let mut icc = jpeg.icc_profile().map(|profile| icc::rewrite(&profile, policy.icc_mode()));
+    let xmp_extensions = kept_xmp_extensions(&jpeg, policy);
+    let mut comments = 0;
+    let mut out = Vec::with_capacity(data.len());
+    for segment in &jpeg.segments {
+        let payload = segment.payload();
+        match segment.marker {
+            APP0 if payload.starts_with(JFIF_HEADER) && payload.len() >= 14 => {
+                // Keep version, units and density, drop the thumbnail
+                let mut jfif = payload[..14].to_vec();
+                jfif[12] = 0;
+                jfif[13] = 0;
+                write_segment(&mut out, APP0, &jfif)?;
+            }
+            APP1 if payload.starts_with(EXIF_HEADER) && !policy.removes_everything() => {
+                if let Some(tiff) = super::filter_exif(&payload[EXIF_HEADER.len()..], policy)? {
+                    write_segment(&mut out, APP1, &[EXIF_HEADER, &tiff].concat())?;
+                }
+            }
+            APP1 if payload.starts_with(XMP_HEADER) && !policy.removes_everything() => {
+                let packet = xmp::filter(&payload[XMP_HEADER.len()..], policy)
+                    .context("Failed to filter the XMP packet (--level all removes it as a whole)")?;
+                match packet {
+                    Some(Cow::Borrowed(_)) => out.extend_from_slice(segment.bytes),
+                    Some(Cow::Owned(packet)) => write_segment(&mut out, APP1, &[XMP_HEADER, &packet].concat())?,
+                    None => {}
+                }
+            }
+            APP1 if payload.starts_with(XMP_EXTENSION_HEADER) => {
+                let guid = payload.get(XMP_EXTENSION_HEADER.len()..XMP_EXTENSION_HEADER.len() + XMP_GUID_LEN);
+                if guid.is_some_and(|guid| xmp_extensions.contains(&guid)) {
+                    out.extend_from_slice(segment.bytes);
+                }
+            }
+            APP2 if payload.starts_with(ICC_HEADER) => match &icc {
+                Some(Rewrite::Keep) => out.extend_from_slice(segment.bytes),
+                // The replacement takes the place of the first segment of the old profile
+                Some(Rewrite::Replace(profile)) => {
+                    write_icc_segments(&mut out, profile)?;
+                    icc = Some(Rewrite::Strip);
+                }
+                _ => {}
+            },
+            APP13 if payload.starts_with(PHOTOSHOP_HEADER) && !policy.removes_everything() => {
+                let resources = iptc::filter_resources(&payload[PHOTOSHOP_HEADER.len()..], policy)
+                    .context("Failed to filter the IPTC records (--level all removes them as a whole)")?;
+                if let Some(resources) = resources {
+                    write_segment(&mut out, APP13, &[PHOTOSHOP_HEADER, &resources].concat())?;
+                }
+            }
+            APP14 if payload.starts_with(ADOBE_HEADER) => out.extend_from_slice(segment.bytes),
+            COM => {
+                comments += 1;
+                if !policy.should_remove(&comment_key(comments)) {
+                    out.extend_from_slice(segment.bytes);
+                }
+            }
+            APP0..=APP15 => {}
+            _ => out.extend_from_slice(segment.bytes),
+        }
+    }
+    if policy.keeps_trailer() {
+        let mut pos = jpeg.end;
+        let mut previews = jpeg.mpf_images(data);
+        previews.sort_by_key(|r| r.start);
+        for preview in previews.into_iter().filter(|r| r.start >= jpeg.end) {
+            out.extend_from_slice(&data[pos.min(preview.start)..preview.start]);
+            pos = pos.max(preview.end);
+        }
+        out.extend_from_slice(&data[pos..]);
+    }
+    Ok(out)
+}
+
+/// Append a marker segment with the given payload
+fn write_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) -> Result<()> {
+    let len = u16::try_from(payload.len() + 2)
+        .map_err(|_| anyhow::anyhow!("JPEG segment too large ({} bytes)", payload.len()))?;
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Append an ICC profile as numbered APP2 segments
+fn write_icc_segments(out: &mut Vec<u8>, profile: &[u8]) -> Result<()> {
+    let chunks: Vec<&[u8]> = profile.chunks(ICC_CHUNK_LEN).collect();
+    let count = u8::try_from(chunks.len()).map_err(|_| anyhow::anyhow!("ICC profile too large ({} bytes)", profile.len()))?;
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        write_segment(out, APP2, &[ICC_HEADER, &[i as u8 + 1, count], chunk].concat())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::policy::PrivacyLevel;
+    use std::io::Cursor;
+    use exif::{Field, In, Tag, Value};
+
+    pub(crate) fn sample_jpeg() -> Vec<u8> {
+        let img = image::RgbImage::from_fn(48, 32, |x, y| {
+            image::Rgb([(x * 5) as u8, (y * 7) as u8, ((x + y) * 3) as u8])
+        });
+        let mut out = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(img)
+            .write_to(&mut out, image::ImageFormat::Jpeg)
+            .unwrap();
+        out.into_inner()
+    }
+
+    /// An EXIF APP1 payload with Orientation, Make and GPSLatitudeRef
+    pub(crate) fn sample_exif() -> Vec<u8> {
+        let fields = [
+            Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) },
+            Field { tag: Tag::Make, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Canon".to_vec()]) },
+            Field { tag: Tag::GPSLatitudeRef, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"N".to_vec()]) },
+        ];
+        let mut writer = exif::experimental::Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        [EXIF_HEADER, &buf.into_inner()].concat()
+    }
+
+    /// Photoshop resources with a thumbnail and IPTC records: version, City and Credit
+    fn sample_photoshop() -> Vec<u8> {
+        let dataset = |number: u8, value: &[u8]| [&[0x1C, 2, number], &(value.len() as u16).to_be_bytes()[..], value].concat();
+        let records = [dataset(0, &[0, 4]), dataset(90, b"Paris"), dataset(110, b"Jane Doe")].concat();
+        let resource = |id: u16, data: &[u8]| {
+            let padding: &[u8] = if data.len() % 2 == 1 { &[0] } else { &[] };
+            [b"8BIM", &id.to_be_bytes()[..], &[0, 0], &(data.len() as u32).to_be_bytes(), data, padding].concat()
+        };
+        [PHOTOSHOP_HEADER, &resource(0x040C, b"thumbnail"), &resource(0x0404, &records)].concat()
+    }
+
+    /// Insert EXIF, XMP, IPTC, ICC and comment segments right after SOI
+    fn with_metadata(jpeg: &[u8]) -> Vec<u8> {
+        let mut out = jpeg[..2].to_vec();
+        write_segment(&mut out, APP1, &sample_exif()).unwrap();
+        write_segment(&mut out, APP1, &[XMP_HEADER, xmp::tests::PACKET].concat()).unwrap();
+        write_segment(&mut out, APP13, &sample_photoshop()).unwrap();
+        write_segment(&mut out, APP2, &[ICC_HEADER, &[1, 1], b"profile"].concat()).unwrap();
+        write_segment(&mut out, COM, b"secret comment").unwrap();
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    /// SOS headers together with their entropy-coded data
+    fn scan_data(data: &[u8]) -> Vec<u8> {
+        let jpeg = Jpeg::parse(data).unwrap();
+        jpeg.segments.iter().filter(|s| s.marker == SOS).flat_map(|s| s.bytes.to_vec()).collect()
+    }
+
+    fn decode(data: &[u8]) -> Vec<u8> {
+        jpeg_decoder::Decoder::new(data).decode().unwrap()
+    }
+
+    #[test]
+    fn clean_copies_scan_data_and_pixels_unchanged() {
+        let original = with_metadata(&sample_jpeg());
+        let policy = RemovalPolicy::new(None, &[], &[]).unwrap();
+        let cleaned = clean(&original, &policy).unwrap();
+        assert!(cleaned.len() < original.len());
+        assert_eq!(scan_data(&original), scan_data(&cleaned));
+        assert_eq!(decode(&original), decode(&cleaned));
+    }
+
+    #[test]
+    fn clean_keeps_green_exif_tags_and_icc_by_default() {
+        let original = with_metadata(&sample_jpeg());
+        let policy = RemovalPolicy::new(None, &[], &[]).unwrap();
+        let cleaned = clean(&original, &policy).unwrap();
+        // The XMP packet is left with its orientation only
+        assert_eq!(containers(&cleaned).unwrap(), vec![Container::Exif, Container::Xmp, Container::Icc]);
+        let packet = String::from_utf8(container_payload(&cleaned, Container::Xmp)).unwrap();
+        assert!(packet.contains("tiff:Orientation") && !packet.contains("dc:"));
+        let exif_data = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(&cleaned))
+            .unwrap();
+        let tags: Vec<Tag> = exif_data.fields().map(|f| f.tag).collect();
+        assert_eq!(tags, vec![Tag::Orientation]);
+    }
+
+    #[test]
+    fn clean_level_all_removes_every_container() {
+        let original = with_metadata(&sample_jpeg());
+        let policy = RemovalPolicy::new(Some(PrivacyLevel::All), &[], &[]).unwrap();
+        let cleaned = clean(&original, &policy).unwrap();
+        assert!(containers(&cleaned).unwrap().is_empty());
+        assert_eq!(decode(&original), decode(&cleaned));
+    }
+
+    /// Payload of the first segment carrying a container
+    fn container_payload(data: &[u8], container: Container) -> Vec<u8> {
+        let jpeg = Jpeg::parse(data).unwrap();
+        jpeg.segments.iter().find(|s| s.container() == Some(container)).unwrap().payload().to_vec()
+    }
+
+    #[test]
+    fn clean_keeps_the_xmp_and_iptc_fields_the_policy_keeps() {
+        let original = with_metadata(&sample_jpeg());
+        let policy = RemovalPolicy::new(None, &[], &["GPS*".to_string()]).unwrap();
+        let cleaned = clean(&original, &policy).unwrap();
+        assert_eq!(
+            containers(&cleaned).unwrap(),
+            vec![Container::Exif, Container::Xmp, Container::Iptc, Container::Icc, Container::Comment]
+        );
+        let packet = String::from_utf8(container_payload(&cleaned, Container::Xmp)).unwrap();
+        assert!(packet.contains("dc:rights") && packet.contains("dc:creator") && !packet.contains("GPSLatitude"));
+        let contains = |data: &[u8], text: &[u8]| data.windows(text.len()).any(|w| w == text);
+        let iptc = container_payload(&cleaned, Container::Iptc);
+        assert!(contains(&iptc, b"Paris") && contains(&iptc, b"Jane Doe") && !contains(&iptc, b"thumbnail"));
+
+        // City is insecure, Credit and the comment only better to remove
+        let red = RemovalPolicy::new(Some(PrivacyLevel::Red), &[], &[]).unwrap();
+        let cleaned = clean(&original, &red).unwrap();
+        let iptc = container_payload(&cleaned, Container::Iptc);
+        assert!(!contains(&iptc, b"Paris") && contains(&iptc, b"Jane Doe"));
+        assert!(containers(&cleaned).unwrap().contains(&Container::Comment));
+    }
+}
@@ -0,0 +1,265 @@
+pub mod gif;
+pub mod heif;
+pub mod icc;
+pub mod iptc;
+pub mod jpeg;
+pub mod png;
+pub mod thumbnail;
+pub mod tiff;
+pub mod trailer;
+pub mod webp;
+pub mod xmp;
+
+use std::fmt;
+use std::io::Cursor;
+use crate::policy::RemovalPolicy;
+use thumbnail::{Thumbnail, ThumbnailKind};
+use trailer::Trailer;
+
+/// Image container formats that medars parses natively
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Gif,
+    Heif,
+    Avif,
+    Tiff,
+}
+
+impl ImageFormat {
+    /// Detect the format from the file's magic bytes
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&jpeg::SOI) {
+            Some(ImageFormat::Jpeg)
+        } else if data.starts_with(&png::SIGNATURE) {
+            Some(ImageFormat::Png)
+        } else if data.len() >= 12 && &data[..4] == webp::RIFF && &data[8..12] == webp::WEBP {
+            Some(ImageFormat::Webp)
+        } else if data.starts_with(gif::GIF87A) || data.starts_with(gif::GIF89A) {
+            Some(ImageFormat::Gif)
+        } else if tiff::is_tiff(data) {
+            Some(ImageFormat::Tiff)
+        } else {
+            heif::brand(data).map(|brand| match brand {
+                heif::Brand::Heif => ImageFormat::Heif,
+                heif::Brand::Avif => ImageFormat::Avif,
+            })
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Png => "PNG",
+            ImageFormat::Webp => "WebP",
+            ImageFormat::Gif => "GIF",
+            ImageFormat::Heif => "HEIF",
+            ImageFormat::Avif => "AVIF",
+            ImageFormat::Tiff => "TIFF",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Kinds of metadata blocks that can be embedded in an image file
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, serde::Serialize, serde::Deserialize)]
+pub enum Container {
+    Exif,
+    Xmp,
+    Iptc,
+    Icc,
+    Comment,
+    Thumbnail,
+    Trailer,
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Container::Exif => "EXIF",
+            Container::Xmp => "XMP",
+            Container::Iptc => "IPTC",
+            Container::Icc => "ICC",
+            Container::Comment => "Comments",
+            Container::Thumbnail => "Thumbnail",
+            Container::Trailer => "Trailing data",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Whether a path has the extension of an image format medars can read
+pub fn is_image_file(path: &std::path::Path) -> bool {
+    if let Some(ext) = path.extension() {
+        match ext.to_string_lossy().to_lowercase().as_str() {
+            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "tif" | "webp" => true,
+            "heic" | "heif" | "hif" | "avif" => true,
+            ext if tiff::RAW_EXTENSIONS.contains(&ext) => true,
+            _ => false,
+        }
+    } else {
+        false
+    }
+}
+
+/// Detect the metadata containers in an in-memory image using the native parsers.
+/// Returns an empty list for formats without a native parser.
+pub fn scan_containers(data: &[u8]) -> anyhow::Result<Vec<Container>> {
+    let mut containers = match ImageFormat::detect(data) {
+        Some(ImageFormat::Jpeg) => jpeg::containers(data)?,
+        Some(ImageFormat::Png) => png::containers(data)?,
+        Some(ImageFormat::Webp) => webp::containers(data)?,
+        Some(ImageFormat::Gif) => gif::containers(data)?,
+        Some(ImageFormat::Heif | ImageFormat::Avif) => heif::containers(data)?,
+        Some(ImageFormat::Tiff) => tiff::containers(data)?,
+        None => Vec::new(),
+    };
+    containers.sort();
+    containers.dedup();
+    Ok(containers)
+}
+
+/// Metadata only the native parsers know about (e.g. PNG text chunks, JPEG and GIF comments, RAW SubIFDs),
+/// as typed entries
+pub fn native_entries(data: &[u8]) -> anyhow::Result<Vec<crate::entry::MetadataEntry>> {
+    match ImageFormat::detect(data) {
+        Some(ImageFormat::Jpeg) => jpeg::comment_entries(data),
+        Some(ImageFormat::Png) => png::chunk_entries(data),
+        Some(ImageFormat::Gif) => gif::block_entries(data),
+        Some(ImageFormat::Tiff) => tiff::entries(data),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Raw EXIF (TIFF) block of an in-memory image, used for thumbnails and for containers
+/// the EXIF reader cannot parse by itself
+pub fn exif_block(data: &[u8]) -> Option<Vec<u8>> {
+    match ImageFormat::detect(data)? {
+        ImageFormat::Jpeg => jpeg::Jpeg::parse(data).ok()?.exif_tiff().map(|tiff| tiff.to_vec()),
+        ImageFormat::Png => png::exif_tiff(data),
+        ImageFormat::Webp => webp::exif_tiff(data),
+        ImageFormat::Heif | ImageFormat::Avif => heif::exif_tiff(data),
+        ImageFormat::Tiff => Some(data.to_vec()),
+        ImageFormat::Gif => None,
+    }
+}
+
+/// Embedded ICC profile, reassembled from its segments or decompressed where needed
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    match ImageFormat::detect(data)? {
+        ImageFormat::Jpeg => jpeg::Jpeg::parse(data).ok()?.icc_profile(),
+        ImageFormat::Png => png::icc_profile(data),
+        ImageFormat::Webp => webp::icc_profile(data),
+        ImageFormat::Gif => gif::icc_profile(data),
+        ImageFormat::Heif | ImageFormat::Avif => heif::icc_profile(data),
+        ImageFormat::Tiff => tiff::icc_profile(data),
+    }
+}
+
+/// Data that follows the logical end of the image (EOI, IEND, the RIFF container, ...).
+/// TIFF-based files have no logical end and are not scanned.
+pub fn trailer(data: &[u8]) -> Option<Trailer> {
+    let end = match ImageFormat::detect(data)? {
+        ImageFormat::Jpeg => jpeg::Jpeg::parse(data).ok()?.end,
+        ImageFormat::Png => png::Png::parse(data).ok()?.end,
+        ImageFormat::Webp => webp::Webp::parse(data).ok()?.end,
+        ImageFormat::Gif => gif::Gif::parse(data).ok()?.end,
+        ImageFormat::Heif | ImageFormat::Avif => heif::Heif::parse(data).ok()?.end,
+        ImageFormat::Tiff => return None,
+    };
+    (end < data.len()).then(|| Trailer::new(end, &data[end..]))
+}
+
+/// Embedded thumbnails and preview images
+pub fn thumbnails(data: &[u8]) -> Vec<Thumbnail> {
+    let mut thumbnails: Vec<Thumbnail> = exif_block(data).and_then(|tiff| thumbnail::from_exif(&tiff)).into_iter().collect();
+    match ImageFormat::detect(data) {
+        Some(ImageFormat::Jpeg) => {
+            let Ok(jpeg) = jpeg::Jpeg::parse(data) else {
+                return thumbnails;
+            };
+            if let Some((width, height, pixels)) = jpeg.jfif_thumbnail() {
+                thumbnails.push(Thumbnail {
+                    kind: ThumbnailKind::Jfif,
+                    dimensions: Some((width, height)),
+                    data: pixels.to_vec(),
+                });
+            }
+            for range in jpeg.mpf_images(data) {
+                let preview = &data[range];
+                thumbnails.push(Thumbnail {
+                    kind: ThumbnailKind::Mpf,
+                    dimensions: jpeg::Jpeg::parse(preview).ok().and_then(|p| p.dimensions()),
+                    data: preview.to_vec(),
+                });
+            }
+        }
+        Some(ImageFormat::Heif | ImageFormat::Avif) => thumbnails.extend(heif::thumbnails(data)),
+        _ => {}
+    }
+    thumbnails
+}
+
+/// Raw XMP packet for containers the XMP reader cannot parse by itself
+pub fn xmp_packet(data: &[u8]) -> Option<Vec<u8>> {
+    match ImageFormat::detect(data)? {
+        ImageFormat::Gif => gif::xmp_packet(data),
+        ImageFormat::Heif | ImageFormat::Avif => heif::xmp_packet(data),
+        _ => None,
+    }
+}
+
+/// Rewrite an image natively, without re-encoding, removing the metadata selected by `policy`
+pub fn clean(format: ImageFormat, data: &[u8], policy: &RemovalPolicy) -> anyhow::Result<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => jpeg::clean(data, policy),
+        ImageFormat::Png => png::clean(data, policy),
+        ImageFormat::Webp => webp::clean(data, policy),
+        ImageFormat::Gif => gif::clean(data, policy),
+        ImageFormat::Heif | ImageFormat::Avif => heif::clean(data, policy),
+        ImageFormat::Tiff => anyhow::bail!("TIFF files are not rewritten natively"),
+    }
+}
+
+/// Whether a raw EXIF (TIFF) block carries an IFD1 thumbnail
+pub(crate) fn exif_has_thumbnail(tiff: &[u8]) -> bool {
+    match exif::Reader::new().read_raw(tiff.to_vec()) {
+        Ok(exif_data) => exif_data.fields().any(|f| f.ifd_num == exif::In::THUMBNAIL),
+        Err(_) => false,
+    }
+}
+
+/// Re-encode a raw EXIF (TIFF) block with only the primary-image fields the policy keeps.
+/// Returns `None` when nothing is left to keep. A block that cannot be parsed or rewritten
+/// is an error rather than dropped, as it may hold the Orientation the image is shown with.
+pub(crate) fn filter_exif(tiff: &[u8], policy: &RemovalPolicy) -> anyhow::Result<Option<Vec<u8>>> {
+    use anyhow::Context;
+    use exif::Tag;
+    let exif_data = exif::Reader::new()
+        .read_raw(tiff.to_vec())
+        .context("Failed to parse the EXIF block (--level all removes it as a whole)")?;
+    let kept: Vec<&exif::Field> = exif_data
+        .fields()
+        .filter(|f| f.ifd_num == exif::In::PRIMARY)
+        // IFD pointers are synthesized by the writer
+        .filter(|f| !matches!(f.tag, Tag::ExifIFDPointer | Tag::GPSInfoIFDPointer | Tag::InteropIFDPointer))
+        .filter(|f| !matches!(f.value, exif::Value::Unknown(..)))
+        .filter(|f| !policy.should_remove(&f.tag.to_string()))
+        .collect();
+    if kept.is_empty() {
+        return Ok(None);
+    }
+    let mut writer = exif::experimental::Writer::new();
+    for field in &kept {
+        writer.push_field(field);
+    }
+    let mut buf = Cursor::new(Vec::new());
+    writer
+        .write(&mut buf, exif_data.little_endian())
+        .context("Failed to rewrite the EXIF block (--level all removes it as a whole)")?;
+    Ok(Some(buf.into_inner()))
+}
@@ -0,0 +1,497 @@
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use anyhow::{bail, Context, Result};
+use super::icc::{self, Rewrite};
+use super::jpeg::{EXIF_HEADER, XMP_HEADER};
+use super::{iptc, xmp, Container};
+use crate::entry::{MetadataEntry, Value};
+use crate::policy::{IccMode, RemovalPolicy};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Ancillary chunks that only affect how colors are rendered (kept unless the policy removes everything)
+const COLOR_CHUNKS: [&[u8; 4]; 5] = [b"iCCP", b"gAMA", b"sRGB", b"cHRM", b"cICP"];
+
+/// Ancillary chunks needed to display the image correctly (transparency, animation, ...)
+const RENDERING_CHUNKS: [&[u8; 4]; 11] = [
+    b"tRNS", b"sBIT", b"bKGD", b"pHYs", b"hIST", b"sPLT", b"acTL", b"fcTL", b"fdAT", b"mDCV", b"cLLI",
+];
+
+/// Keyword of the iTXt chunk holding an XMP packet
+const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+/// Keyword prefix of the hex-encoded profiles ImageMagick and exiftool store in text chunks
+const RAW_PROFILE_PREFIX: &str = "Raw profile type ";
+/// Hex digits per line of a raw profile
+const RAW_PROFILE_LINE: usize = 72;
+
+/// A chunk of a PNG file
+pub struct Chunk<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+    /// Raw bytes of the whole chunk (length, type, data and CRC)
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    pub fn kind_str(&self) -> &str {
+        std::str::from_utf8(&self.kind).unwrap_or("????")
+    }
+
+    /// Critical chunks (uppercase first letter) must be kept for the image to decode
+    pub fn is_critical(&self) -> bool {
+        self.kind[0].is_ascii_uppercase()
+    }
+
+    /// Decoded text of a tEXt, zTXt or iTXt chunk
+    pub fn text(&self) -> Option<String> {
+        let keyword_end = self.data.iter().position(|&b| b == 0)?;
+        let rest = &self.data[keyword_end + 1..];
+        match &self.kind {
+            b"tEXt" => Some(latin1(rest)),
+            // Compression method byte, then zlib data
+            b"zTXt" => inflate(rest.get(1..)?).map(|text| latin1(&text)),
+            b"iTXt" => {
+                // Compression flag, compression method, language tag, translated keyword, text
+                let (flag, rest) = (*rest.first()?, rest.get(2..)?);
+                let language_end = rest.iter().position(|&b| b == 0)?;
+                let rest = &rest[language_end + 1..];
+                let translated_end = rest.iter().position(|&b| b == 0)?;
+                let text = &rest[translated_end + 1..];
+                if flag == 1 {
+                    inflate(text).map(|text| String::from_utf8_lossy(&text).into_owned())
+                } else {
+                    Some(String::from_utf8_lossy(text).into_owned())
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Keyword of a tEXt, zTXt or iTXt chunk
+    pub fn text_keyword(&self) -> Option<&'a str> {
+        match &self.kind {
+            b"tEXt" | b"zTXt" | b"iTXt" => {
+                let end = self.data.iter().position(|&b| b == 0)?;
+                std::str::from_utf8(&self.data[..end]).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Metadata container carried by this chunk, if any
+    pub fn container(&self) -> Option<Container> {
+        match &self.kind {
+            b"eXIf" => Some(Container::Exif),
+            b"iCCP" => Some(Container::Icc),
+            b"tEXt" | b"zTXt" | b"iTXt" => {
+                // ImageMagick and exiftool store raw profiles in text chunks
+                let container = match self.text_keyword().unwrap_or("") {
+                    XMP_KEYWORD | "Raw profile type xmp" => Container::Xmp,
+                    "Raw profile type iptc" | "Raw profile type 8bim" => Container::Iptc,
+                    "Raw profile type exif" | "Raw profile type APP1" => Container::Exif,
+                    "Raw profile type icc" | "Raw profile type icm" => Container::Icc,
+                    _ => Container::Comment,
+                };
+                Some(container)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A parsed PNG file: its chunks up to IEND and the offset where the image ends
+pub struct Png<'a> {
+    pub chunks: Vec<Chunk<'a>>,
+    /// Offset just past the IEND chunk; anything after it is trailing data
+    pub end: usize,
+}
+
+impl<'a> Png<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(&SIGNATURE) {
+            bail!("Not a PNG file");
+        }
+        let mut chunks = Vec::new();
+        let mut pos = SIGNATURE.len();
+        while pos < data.len() {
+            if pos + 12 > data.len() {
+                bail!("Truncated PNG chunk at offset {}", pos);
+            }
+            let len = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+            let end = pos
+                .checked_add(12)
+                .and_then(|p| p.checked_add(len))
+                .filter(|&end| end <= data.len());
+            let Some(end) = end else {
+                bail!("Invalid PNG chunk length at offset {}", pos);
+            };
+            let kind = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+            chunks.push(Chunk { kind, data: &data[pos + 8..pos + 8 + len], bytes: &data[pos..end] });
+            pos = end;
+            if &kind == b"IEND" {
+                break;
+            }
+        }
+        Ok(Png { chunks, end: pos })
+    }
+
+    /// Bytes after the IEND chunk
+    pub fn trailer(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.end..]
+    }
+}
+
+/// Detect the metadata containers in a PNG file
+pub fn containers(data: &[u8]) -> Result<Vec<Container>> {
+    let png = Png::parse(data)?;
+    let mut found = Vec::new();
+    for chunk in &png.chunks {
+        if let Some(container) = chunk.container() {
+            found.push(container);
+            if &chunk.kind == b"eXIf" && super::exif_has_thumbnail(chunk.data) {
+                found.push(Container::Thumbnail);
+            }
+        }
+    }
+    if !png.trailer(data).is_empty() {
+        found.push(Container::Trailer);
+    }
+    Ok(found)
+}
+
+/// List the chunks `clean` may remove: text chunks (keyed `Png.tEXt.<keyword>`), tIME and
+/// unknown ancillary chunks (keyed `Png.<type>`). eXIf is read by the EXIF reader.
+pub fn chunk_entries(data: &[u8]) -> Result<Vec<MetadataEntry>> {
+    let png = Png::parse(data)?;
+    let mut entries = Vec::new();
+    for chunk in &png.chunks {
+        let kind = chunk.kind_str();
+        match &chunk.kind {
+            b"tEXt" | b"zTXt" | b"iTXt" => {
+                let keyword = chunk.text_keyword().unwrap_or("");
+                let key = format!("Png.{}.{}", kind, keyword);
+                let entry = match chunk.text() {
+                    Some(text) => MetadataEntry::new(chunk.container(), kind, keyword, key, Value::Text(text)),
+                    None => byte_count(chunk, keyword, key),
+                };
+                entries.push(entry);
+            }
+            b"tIME" if chunk.data.len() == 7 => {
+                let d = chunk.data;
+                let year = u16::from_be_bytes([d[0], d[1]]);
+                let time = format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, d[2], d[3], d[4], d[5], d[6]);
+                entries.push(MetadataEntry::new(None, kind, kind, "Png.tIME".to_string(), Value::Text(time)));
+            }
+            b"eXIf" => {}
+            _ if is_removable_ancillary(chunk) => entries.push(byte_count(chunk, kind, format!("Png.{}", kind))),
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// Entry for a chunk whose content is not displayed, only its size
+fn byte_count(chunk: &Chunk, name: &str, key: String) -> MetadataEntry {
+    let len = chunk.data.len();
+    MetadataEntry::new(chunk.container(), chunk.kind_str(), name, key, Value::Integer(len as i64))
+        .with_display(format!("{} bytes", len))
+}
+
+/// Raw TIFF block of the eXIf chunk, if any
+pub fn exif_tiff(data: &[u8]) -> Option<Vec<u8>> {
+    let png = Png::parse(data).ok()?;
+    png.chunks.iter().find(|c| &c.kind == b"eXIf").map(|c| c.data.to_vec())
+}
+
+/// Decompressed profile of the iCCP chunk, if any
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let png = Png::parse(data).ok()?;
+    let chunk = png.chunks.iter().find(|c| &c.kind == b"iCCP")?;
+    // Profile name, compression method byte, then zlib data
+    let name_end = chunk.data.iter().position(|&b| b == 0)?;
+    inflate(chunk.data.get(name_end + 2..)?)
+}
+
+/// Rewrite a PNG without the text chunks, tIME and unknown ancillary chunks the policy removes,
+/// by their key in `chunk_entries`.
+///
+/// Critical chunks and the ancillary chunks needed for rendering are copied as they are.
+/// eXIf is rebuilt with only the tags the policy keeps (with a recomputed CRC), iCCP follows
+/// the policy's ICC mode, and the other color chunks (gAMA, sRGB, ...) are kept unless the
+/// policy removes everything. Text chunks holding an XMP packet or a raw profile keep the
+/// fields the policy keeps. Data after IEND is dropped unless the policy keeps it.
+pub fn clean(data: &[u8], policy: &RemovalPolicy) -> Result<Vec<u8>> {
+    let png = Png::parse(data)?;
+    let icc = match icc_profile(data) {
+        Some(profile) => icc::rewrite(&profile, policy.icc_mode()),
+        // A profile that does not decompress can still be dropped
+        None if policy.icc_mode() == IccMode::Strip => Rewrite::Strip,
+        None => Rewrite::Keep,
+    };
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&SIGNATURE);
+    for chunk in &png.chunks {
+        match &chunk.kind {
+            b"eXIf" if !policy.removes_everything() => {
+                if let Some(tiff) = super::filter_exif(chunk.data, policy)? {
+                    write_chunk(&mut out, b"eXIf", &tiff)?;
+                }
+            }
+            b"iCCP" => match &icc {
+                Rewrite::Keep => out.extend_from_slice(chunk.bytes),
+                Rewrite::Strip => {}
+                Rewrite::Replace(profile) => {
+                    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+                    encoder.write_all(profile)?;
+                    let compressed = encoder.finish()?;
+                    write_chunk(&mut out, b"iCCP", &[icc::SRGB_DESCRIPTION.as_bytes(), &[0, 0], &compressed].concat())?;
+                }
+            },
+            kind if COLOR_CHUNKS.contains(&kind) && policy.removes_everything() => {}
+            _ if !is_removable_ancillary(chunk) => out.extend_from_slice(chunk.bytes),
+            _ if policy.removes_everything() => {}
+            b"tEXt" | b"zTXt" | b"iTXt" if chunk.container() != Some(Container::Comment) => {
+                if let Some(bytes) = filter_text_container(chunk, policy)? {
+                    out.extend_from_slice(&bytes);
+                }
+            }
+            _ => {
+                let key = match chunk.text_keyword() {
+                    Some(keyword) => format!("Png.{}.{}", chunk.kind_str(), keyword),
+                    None => format!("Png.{}", chunk.kind_str()),
+                };
+                if !policy.should_remove(&key) {
+                    out.extend_from_slice(chunk.bytes);
+                }
+            }
+        }
+    }
+    if policy.keeps_trailer() {
+        out.extend_from_slice(png.trailer(data));
+    }
+    Ok(out)
+}
+
+/// Rewrite a text chunk holding a metadata container (an XMP packet, or a raw profile of EXIF,
+/// IPTC, XMP or ICC data) with only the fields the policy keeps. Returns the bytes of the
+/// chunk to write, or `None` to drop it.
+fn filter_text_container<'a>(chunk: &Chunk<'a>, policy: &RemovalPolicy) -> Result<Option<Cow<'a, [u8]>>> {
+    let keyword = chunk.text_keyword().unwrap_or("");
+    let Some(text) = chunk.text() else {
+        return Ok(None);
+    };
+    if keyword == XMP_KEYWORD {
+        let packet = xmp::filter(text.as_bytes(), policy)
+            .context("Failed to filter the XMP packet (--level all removes it as a whole)")?;
+        return match packet {
+            Some(Cow::Borrowed(_)) => Ok(Some(Cow::Borrowed(chunk.bytes))),
+            // Uncompressed, without language tag or translated keyword
+            Some(Cow::Owned(packet)) => {
+                let data = [XMP_KEYWORD.as_bytes(), &[0, 0, 0, 0, 0], &packet].concat();
+                let mut out = Vec::new();
+                write_chunk(&mut out, b"iTXt", &data)?;
+                Ok(Some(Cow::Owned(out)))
+            }
+            None => Ok(None),
+        };
+    }
+    let Some(profile_type) = keyword.strip_prefix(RAW_PROFILE_PREFIX) else {
+        return Ok(None);
+    };
+    // A profile that does not decode cannot be filtered, and goes
+    let Some(profile) = decode_raw_profile(&text) else {
+        return Ok(None);
+    };
+    let filtered: Option<Cow<[u8]>> = match profile_type {
+        "exif" | "APP1" if profile.starts_with(EXIF_HEADER) => super::filter_exif(&profile[EXIF_HEADER.len()..], policy)?
+            .map(|tiff| Cow::Owned([EXIF_HEADER, &tiff].concat())),
+        "exif" => super::filter_exif(&profile, policy)?.map(Cow::Owned),
+        "xmp" | "APP1" => {
+            let (header, packet) = match profile.strip_prefix(XMP_HEADER) {
+                Some(packet) => (XMP_HEADER, packet),
+                None => (&[][..], &profile[..]),
+            };
+            xmp::filter(packet, policy)?.map(|packet| match packet {
+                Cow::Borrowed(_) => Cow::Borrowed(&profile[..]),
+                Cow::Owned(packet) => Cow::Owned([header, &packet].concat()),
+            })
+        }
+        "8bim" => iptc::filter_resources(&profile, policy)?.map(Cow::Owned),
+        "iptc" if profile.starts_with(b"8BIM") => iptc::filter_resources(&profile, policy)?.map(Cow::Owned),
+        "iptc" => iptc::filter_records(&profile, policy)?.map(Cow::Owned),
+        "icc" | "icm" if policy.icc_mode() == IccMode::Keep => Some(Cow::Borrowed(&profile[..])),
+        _ => None,
+    };
+    match filtered {
+        Some(Cow::Borrowed(_)) => Ok(Some(Cow::Borrowed(chunk.bytes))),
+        Some(Cow::Owned(profile)) => {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(encode_raw_profile(profile_type, &profile).as_bytes())?;
+            let data = [keyword.as_bytes(), &[0, 0], &encoder.finish()?].concat();
+            let mut out = Vec::new();
+            write_chunk(&mut out, b"zTXt", &data)?;
+            Ok(Some(Cow::Owned(out)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Bytes of a raw profile: a blank line, the profile type, its length in bytes and then
+/// the data in hex, split in lines
+fn decode_raw_profile(text: &str) -> Option<Vec<u8>> {
+    let mut lines = text.trim_start_matches('\n').splitn(3, '\n');
+    let _profile_type = lines.next()?;
+    let len: usize = lines.next()?.trim().parse().ok()?;
+    let hex: Vec<u8> = lines.next()?.bytes().filter(|b| b.is_ascii_hexdigit()).collect();
+    if hex.len() < len * 2 {
+        return None;
+    }
+    hex.chunks(2)
+        .take(len)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Text of a raw profile, laid out like ImageMagick does
+fn encode_raw_profile(profile_type: &str, data: &[u8]) -> String {
+    let mut text = format!("\n{}\n{:8}\n", profile_type, data.len());
+    for (i, byte) in data.iter().enumerate() {
+        if i > 0 && i % (RAW_PROFILE_LINE / 2) == 0 {
+            text.push('\n');
+        }
+        text.push_str(&format!("{:02x}", byte));
+    }
+    text.push('\n');
+    text
+}
+
+/// Ancillary chunks that carry no rendering information (text, tIME, eXIf and unknown chunks)
+fn is_removable_ancillary(chunk: &Chunk) -> bool {
+    !chunk.is_critical() && !COLOR_CHUNKS.contains(&&chunk.kind) && !RENDERING_CHUNKS.contains(&&chunk.kind)
+}
+
+/// Append a chunk with the given data and its CRC
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    let len = u32::try_from(data.len())
+        .map_err(|_| anyhow::anyhow!("PNG chunk too large ({} bytes)", data.len()))?;
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+    Ok(())
+}
+
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    flate2::read::ZlibDecoder::new(data).read_to_end(&mut out).ok()?;
+    Some(out)
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PrivacyLevel;
+    use std::io::Cursor;
+
+    /// An RGB PNG with transparency, animation and text chunks, tIME, a private chunk, EXIF
+    /// (in eXIf and in a raw profile) and an XMP packet
+    fn sample_png() -> Vec<u8> {
+        let img = image::RgbImage::from_fn(8, 8, |x, y| image::Rgb([x as u8 * 30, y as u8 * 30, 0]));
+        let mut encoded = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(img).write_to(&mut encoded, image::ImageFormat::Png).unwrap();
+        let encoded = encoded.into_inner();
+        let exif = super::super::jpeg::tests::sample_exif();
+        let mut raw_profile = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        raw_profile.write_all(encode_raw_profile("exif", &exif).as_bytes()).unwrap();
+        let raw_profile = [b"Raw profile type exif\0\0", &raw_profile.finish().unwrap()[..]].concat();
+        let xmp = [XMP_KEYWORD.as_bytes(), &[0, 0, 0, 0, 0], xmp::tests::PACKET].concat();
+        let mut out = SIGNATURE.to_vec();
+        for chunk in &Png::parse(&encoded).unwrap().chunks {
+            if &chunk.kind == b"IDAT" {
+                let metadata: [(&[u8; 4], &[u8]); 9] = [
+                    (b"tRNS", &[0, 1, 0, 2, 0, 3]),
+                    (b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]),
+                    (b"tEXt", b"Author\0Jane Doe"),
+                    (b"tEXt", b"Software\0medars test"),
+                    (b"tIME", &[7, 232, 5, 1, 12, 0, 0]),
+                    (b"prVt", b"private"),
+                    (b"eXIf", &exif[EXIF_HEADER.len()..]),
+                    (b"zTXt", &raw_profile),
+                    (b"iTXt", &xmp),
+                ];
+                for (kind, data) in metadata {
+                    write_chunk(&mut out, kind, data).unwrap();
+                }
+            }
+            out.extend_from_slice(chunk.bytes);
+            if &chunk.kind == b"IDAT" {
+                write_chunk(&mut out, b"fdAT", &[0, 0, 0, 1, 0]).unwrap();
+            }
+        }
+        out
+    }
+
+    fn kinds(png: &Png) -> Vec<String> {
+        png.chunks.iter().map(|c| c.kind_str().to_string()).collect()
+    }
+
+    fn crc_is_valid(chunk: &Chunk) -> bool {
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&chunk.kind);
+        crc.update(chunk.data);
+        chunk.bytes[chunk.bytes.len() - 4..] == crc.finalize().to_be_bytes()
+    }
+
+    fn text(png: &Png, keyword: &str) -> Option<String> {
+        png.chunks.iter().find(|c| c.text_keyword() == Some(keyword)).and_then(|c| c.text())
+    }
+
+    #[test]
+    fn clean_keeps_rendering_chunks_with_valid_crcs() {
+        let original = sample_png();
+        let policy = RemovalPolicy::new(None, &[], &[]).unwrap();
+        let cleaned = clean(&original, &policy).unwrap();
+        let png = Png::parse(&cleaned).unwrap();
+        // Only the orientation is left of the EXIF and XMP fields
+        assert_eq!(kinds(&png), ["IHDR", "tRNS", "acTL", "eXIf", "zTXt", "iTXt", "IDAT", "fdAT", "IEND"]);
+        assert!(png.chunks.iter().all(crc_is_valid));
+        let idat = |png: &Png| png.chunks.iter().filter(|c| &c.kind == b"IDAT").map(|c| c.bytes.to_vec()).collect::<Vec<_>>();
+        assert_eq!(idat(&png), idat(&Png::parse(&original).unwrap()));
+        let packet = text(&png, XMP_KEYWORD).unwrap();
+        assert!(packet.contains("tiff:Orientation") && !packet.contains("dc:rights"));
+    }
+
+    #[test]
+    fn clean_removes_text_chunks_by_policy() {
+        let original = sample_png();
+        let strip_gps = RemovalPolicy::new(None, &[], &["GPS*".to_string()]).unwrap();
+        let png_data = clean(&original, &strip_gps).unwrap();
+        let png = Png::parse(&png_data).unwrap();
+        assert_eq!(
+            kinds(&png),
+            ["IHDR", "tRNS", "acTL", "tEXt", "tEXt", "tIME", "prVt", "eXIf", "zTXt", "iTXt", "IDAT", "fdAT", "IEND"]
+        );
+        assert!(png.chunks.iter().all(crc_is_valid));
+        let profile = decode_raw_profile(&text(&png, "Raw profile type exif").unwrap()).unwrap();
+        let exif_data = exif::Reader::new().read_raw(profile[EXIF_HEADER.len()..].to_vec()).unwrap();
+        let mut tags: Vec<u16> = exif_data.fields().map(|f| f.tag.number()).collect();
+        tags.sort();
+        assert_eq!(tags, [exif::Tag::Make.number(), exif::Tag::Orientation.number()]);
+        assert!(!text(&png, XMP_KEYWORD).unwrap().contains("GPSLatitude"));
+
+        // Author and tIME are insecure, Software only better to remove
+        let red = RemovalPolicy::new(Some(PrivacyLevel::Red), &[], &[]).unwrap();
+        let png_data = clean(&original, &red).unwrap();
+        let png = Png::parse(&png_data).unwrap();
+        assert_eq!(text(&png, "Author"), None);
+        assert_eq!(text(&png, "Software").as_deref(), Some("medars test"));
+        assert!(!kinds(&png).contains(&"tIME".to_string()));
+    }
+}
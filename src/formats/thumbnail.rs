@@ -0,0 +1,68 @@
+use std::fmt;
+use exif::{In, Tag};
+use super::jpeg::Jpeg;
+
+/// Where an embedded preview image comes from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThumbnailKind {
+    /// JPEG in the EXIF IFD1
+    Exif,
+    /// Image indexed by a JPEG MPF segment, stored after EOI
+    Mpf,
+    /// Uncompressed RGB thumbnail of the JFIF header
+    Jfif,
+    /// HEIF/AVIF item referenced as the thumbnail of the primary image
+    Heif,
+}
+
+impl fmt::Display for ThumbnailKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ThumbnailKind::Exif => "EXIF Thumbnail",
+            ThumbnailKind::Mpf => "MPF Preview",
+            ThumbnailKind::Jfif => "JFIF Thumbnail",
+            ThumbnailKind::Heif => "HEIF Thumbnail",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An embedded thumbnail or preview image, which may still show the original, uncropped photo
+pub struct Thumbnail {
+    pub kind: ThumbnailKind,
+    pub dimensions: Option<(u32, u32)>,
+    /// JPEG data, raw RGB pixels for JFIF, or a coded HEVC/AV1 item for HEIF
+    pub data: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// Short description for display, e.g. `160x120, 5230 bytes`
+    pub fn describe(&self) -> String {
+        match self.dimensions {
+            Some((width, height)) => format!("{}x{}, {} bytes", width, height, self.data.len()),
+            None => format!("{} bytes", self.data.len()),
+        }
+    }
+
+    /// Decode the thumbnail for previewing. HEIF items are bare codec streams and are not decoded.
+    pub fn to_image(&self) -> Option<image::DynamicImage> {
+        match self.kind {
+            ThumbnailKind::Exif | ThumbnailKind::Mpf => image::load_from_memory(&self.data).ok(),
+            ThumbnailKind::Jfif => {
+                let (width, height) = self.dimensions?;
+                image::RgbImage::from_raw(width, height, self.data.clone()).map(image::DynamicImage::ImageRgb8)
+            }
+            ThumbnailKind::Heif => None,
+        }
+    }
+}
+
+/// JPEG thumbnail of the IFD1 of a raw EXIF (TIFF) block
+pub fn from_exif(tiff: &[u8]) -> Option<Thumbnail> {
+    let exif_data = exif::Reader::new().read_raw(tiff.to_vec()).ok()?;
+    let offset = exif_data.get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+    let length = exif_data.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+    let data = tiff.get(offset..offset.checked_add(length)?)?.to_vec();
+    let dimensions = Jpeg::parse(&data).ok().and_then(|jpeg| jpeg.dimensions());
+    Some(Thumbnail { kind: ThumbnailKind::Exif, dimensions, data })
+}
@@ -0,0 +1,334 @@
+use std::collections::HashSet;
+use std::path::Path;
+use anyhow::{bail, Result};
+use exif::{Context, Field, In, Rational, SRational, Tag, Value};
+use super::Container;
+use crate::entry::{MetadataEntry, Value as EntryValue};
+
+/// Extensions of TIFF-based camera RAW formats
+pub const RAW_EXTENSIONS: [&str; 10] = ["dng", "cr2", "nef", "nrw", "arw", "srf", "sr2", "pef", "orf", "rw2"];
+
+const TAG_PHOTOMETRIC: u16 = 0x0106;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_XMP: u16 = 0x02BC;
+const TAG_IPTC: u16 = 0x83BB;
+const TAG_ICC: u16 = 0x8773;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DNG_VERSION: u16 = 0xC612;
+
+/// PhotometricInterpretation values of sensor data: color filter array and linear raw
+const PHOTOMETRIC_CFA: u16 = 32803;
+const PHOTOMETRIC_LINEAR_RAW: u16 = 34892;
+
+/// Values with more elements than this are summarized instead of listed (strip offsets, tables, ...)
+const MAX_DISPLAYED_VALUES: usize = 32;
+
+/// Whether the data starts with a TIFF header, including the variants used by
+/// Olympus (`IIRO`) and Panasonic (`IIU\0`) RAW files
+pub fn is_tiff(data: &[u8]) -> bool {
+    data.len() >= 8 && Tiff::header(data).is_some()
+}
+
+/// Whether a TIFF file is a camera RAW, judging from its extension, its signatures and
+/// whether any of its IFDs holds sensor data (NEF and ARW look like plain TIFFs otherwise)
+pub fn is_raw(path: &Path, data: &[u8]) -> bool {
+    let by_extension = path
+        .extension()
+        .is_some_and(|ext| RAW_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()));
+    let Ok(tiff) = Tiff::parse(data) else {
+        return by_extension;
+    };
+    // CR2 stores "CR" right after the header; ORF and RW2 use their own magic numbers
+    by_extension
+        || tiff.magic != 42
+        || data.get(8..10) == Some(b"CR")
+        || tiff.ifds.first().is_some_and(|ifd| ifd.entry(TAG_DNG_VERSION).is_some())
+        || tiff.ifds.iter().any(|ifd| tiff.holds_sensor_data(data, ifd))
+}
+
+/// A directory entry: tag, type, count and where its value lives
+#[derive(Clone, Copy)]
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    /// Offset of the value: inline in the entry, or pointed to by it
+    offset: usize,
+}
+
+/// An image file directory, with the name exiv2 gives its group (`Image`, `SubImage1`, ...)
+struct Ifd {
+    group: String,
+    entries: Vec<Entry>,
+}
+
+impl Ifd {
+    fn entry(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.tag == tag)
+    }
+}
+
+/// A parsed TIFF structure: every IFD reachable from IFD0
+struct Tiff {
+    little_endian: bool,
+    magic: u16,
+    ifds: Vec<Ifd>,
+}
+
+impl Tiff {
+    /// Byte order, magic number and offset of IFD0
+    fn header(data: &[u8]) -> Option<(bool, u16, usize)> {
+        let little_endian = match &data[..2] {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let magic = read_u16(data, 2, little_endian)?;
+        if !matches!(magic, 42 | 0x4F52 | 0x5352 | 0x55) {
+            return None;
+        }
+        Some((little_endian, magic, read_u32(data, 4, little_endian)? as usize))
+    }
+
+    /// Walk the IFD0 chain, SubIFDs and the EXIF and GPS IFDs, guarding against loops
+    fn parse(data: &[u8]) -> Result<Self> {
+        let Some((little_endian, magic, first)) = (data.len() >= 8).then(|| Tiff::header(data)).flatten() else {
+            bail!("Not a TIFF file");
+        };
+        let mut tiff = Tiff { little_endian, magic, ifds: Vec::new() };
+        let mut visited = HashSet::new();
+        let mut sub_images = 0;
+        // (group, offset, position in the IFD0 chain)
+        let mut queue = vec![("Image".to_string(), first, Some(0))];
+        while let Some((group, offset, chain)) = queue.pop() {
+            if offset == 0 || !visited.insert(offset) {
+                continue;
+            }
+            let Some((entries, next)) = tiff.read_ifd(data, offset) else {
+                continue;
+            };
+            if let Some(index) = chain {
+                let next_group = if index == 0 { "Thumbnail".to_string() } else { format!("Image{}", index + 1) };
+                queue.push((next_group, next, Some(index + 1)));
+            }
+            for entry in &entries {
+                match entry.tag {
+                    TAG_SUB_IFDS => {
+                        for sub in tiff.offsets(data, entry) {
+                            sub_images += 1;
+                            queue.push((format!("SubImage{}", sub_images), sub, None));
+                        }
+                    }
+                    TAG_EXIF_IFD => queue.extend(tiff.offsets(data, entry).map(|o| ("Photo".to_string(), o, None))),
+                    TAG_GPS_IFD => queue.extend(tiff.offsets(data, entry).map(|o| ("GPSInfo".to_string(), o, None))),
+                    _ => {}
+                }
+            }
+            tiff.ifds.push(Ifd { group, entries });
+        }
+        Ok(tiff)
+    }
+
+    /// Read the entries of the IFD at `offset` and the offset of the next IFD in the chain
+    fn read_ifd(&self, data: &[u8], offset: usize) -> Option<(Vec<Entry>, usize)> {
+        let count = read_u16(data, offset, self.little_endian)? as usize;
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let at = offset + 2 + i * 12;
+            let tag = read_u16(data, at, self.little_endian)?;
+            let kind = read_u16(data, at + 2, self.little_endian)?;
+            let count = read_u32(data, at + 4, self.little_endian)?;
+            let size = type_size(kind).map(|s| s * count as usize);
+            let offset = match size {
+                Some(size) if size <= 4 => at + 8,
+                _ => read_u32(data, at + 8, self.little_endian)? as usize,
+            };
+            entries.push(Entry { tag, kind, count, offset });
+        }
+        let next = read_u32(data, offset + 2 + count * 12, self.little_endian).unwrap_or(0) as usize;
+        Some((entries, next))
+    }
+
+    /// Whether an IFD is a RAW image, from its PhotometricInterpretation
+    fn holds_sensor_data(&self, data: &[u8], ifd: &Ifd) -> bool {
+        ifd.entry(TAG_PHOTOMETRIC)
+            .filter(|entry| entry.kind == 3)
+            .and_then(|entry| read_u16(data, entry.offset, self.little_endian))
+            .is_some_and(|photometric| matches!(photometric, PHOTOMETRIC_CFA | PHOTOMETRIC_LINEAR_RAW))
+    }
+
+    /// IFD offsets stored in a LONG or IFD entry
+    fn offsets<'a>(&'a self, data: &'a [u8], entry: &'a Entry) -> impl Iterator<Item = usize> + 'a {
+        let valid = matches!(entry.kind, 4 | 13);
+        (0..if valid { entry.count as usize } else { 0 })
+            .filter_map(move |i| read_u32(data, entry.offset + i * 4, self.little_endian))
+            .map(|o| o as usize)
+    }
+
+    /// Decode an entry into a kamadak-exif value, so it can be displayed like the EXIF reader's
+    fn value(&self, data: &[u8], entry: &Entry) -> Option<Value> {
+        let le = self.little_endian;
+        let n = entry.count as usize;
+        let bytes = data.get(entry.offset..entry.offset.checked_add(type_size(entry.kind)? * n)?)?;
+        let at = |i: usize, size: usize| &bytes[i * size..(i + 1) * size];
+        let u16_at = |i| read_u16(at(i, 2), 0, le).unwrap_or(0);
+        let u32_at = |i, size| read_u32(at(i, size), 0, le).unwrap_or(0);
+        Some(match entry.kind {
+            1 => Value::Byte(bytes.to_vec()),
+            2 => Value::Ascii(bytes.split(|&b| b == 0).filter(|s| !s.is_empty()).map(|s| s.to_vec()).collect()),
+            3 => Value::Short((0..n).map(u16_at).collect()),
+            4 | 13 => Value::Long((0..n).map(|i| u32_at(i, 4)).collect()),
+            5 => Value::Rational(
+                (0..n).map(|i| Rational { num: u32_at(2 * i, 4), denom: u32_at(2 * i + 1, 4) }).collect(),
+            ),
+            6 => Value::SByte(bytes.iter().map(|&b| b as i8).collect()),
+            7 => Value::Undefined(bytes.to_vec(), entry.offset as u32),
+            8 => Value::SShort((0..n).map(|i| u16_at(i) as i16).collect()),
+            9 => Value::SLong((0..n).map(|i| u32_at(i, 4) as i32).collect()),
+            10 => Value::SRational(
+                (0..n)
+                    .map(|i| SRational { num: u32_at(2 * i, 4) as i32, denom: u32_at(2 * i + 1, 4) as i32 })
+                    .collect(),
+            ),
+            11 => Value::Float((0..n).map(|i| f32::from_bits(u32_at(i, 4))).collect()),
+            12 => Value::Double(
+                (0..n)
+                    .map(|i| {
+                        let b: [u8; 8] = at(i, 8).try_into().unwrap();
+                        f64::from_bits(if le { u64::from_le_bytes(b) } else { u64::from_be_bytes(b) })
+                    })
+                    .collect(),
+            ),
+            _ => return None,
+        })
+    }
+}
+
+/// Detect the metadata containers in a TIFF/RAW file
+pub fn containers(data: &[u8]) -> Result<Vec<Container>> {
+    let tiff = Tiff::parse(data)?;
+    let mut found = Vec::new();
+    for ifd in &tiff.ifds {
+        for entry in &ifd.entries {
+            match entry.tag {
+                TAG_EXIF_IFD | TAG_GPS_IFD => found.push(Container::Exif),
+                TAG_XMP => found.push(Container::Xmp),
+                TAG_IPTC => found.push(Container::Iptc),
+                TAG_ICC => found.push(Container::Icc),
+                _ => {}
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// ICC profile of the InterColorProfile tag of IFD0, if any
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let tiff = Tiff::parse(data).ok()?;
+    let entry = tiff.ifds.first()?.entry(TAG_ICC)?;
+    let end = entry.offset.checked_add(entry.count as usize * type_size(entry.kind)?)?;
+    data.get(entry.offset..end).map(|profile| profile.to_vec())
+}
+
+/// Tags of the IFDs the EXIF reader does not visit: SubIFDs (RAW sensor data and previews),
+/// and every IFD of files whose magic number it rejects (ORF, RW2).
+/// Keys follow exiv2 (`Exif.SubImage1.ImageWidth`).
+pub fn entries(data: &[u8]) -> Result<Vec<MetadataEntry>> {
+    let tiff = Tiff::parse(data)?;
+    let mut entries = Vec::new();
+    for (index, ifd) in tiff.ifds.iter().enumerate() {
+        if tiff.magic == 42 && !ifd.group.starts_with("SubImage") {
+            continue;
+        }
+        for entry in &ifd.entries {
+            let context = match ifd.group.as_str() {
+                "Photo" => Context::Exif,
+                "GPSInfo" => Context::Gps,
+                _ => Context::Tiff,
+            };
+            let tag = Tag(context, entry.tag);
+            let name = if tag.description().is_some() { tag.to_string() } else { format!("0x{:04x}", entry.tag) };
+            let key = format!("Exif.{}.{}", ifd.group, name);
+            let count = entry.count as i64;
+            let metadata_entry = if entry.kind == 7 && entry.count as usize > MAX_DISPLAYED_VALUES {
+                MetadataEntry::new(Some(Container::Exif), &ifd.group, &name, key, EntryValue::Integer(count))
+                    .with_display(format!("{} bytes", entry.count))
+            } else if entry.kind != 2 && entry.count as usize > MAX_DISPLAYED_VALUES {
+                MetadataEntry::new(Some(Container::Exif), &ifd.group, &name, key, EntryValue::Integer(count))
+                    .with_display(format!("{} values", entry.count))
+            } else {
+                let Some(value) = tiff.value(data, entry) else {
+                    continue;
+                };
+                let field = Field { tag, ifd_num: In(index as u16), value };
+                let display = field.display_value().with_unit(&field).to_string();
+                let typed = EntryValue::from_exif(&field.value, &display);
+                let metadata_entry = MetadataEntry::new(Some(Container::Exif), &ifd.group, &name, key, typed);
+                match field.value {
+                    exif::Value::Undefined(bytes, _) => metadata_entry.with_raw(bytes),
+                    _ => metadata_entry,
+                }
+                .with_display(display)
+            };
+            entries.push(metadata_entry.with_tag_id(entry.tag as u32));
+        }
+    }
+    Ok(entries)
+}
+
+fn type_size(kind: u16) -> Option<usize> {
+    match kind {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+pub(crate) fn read_u16(data: &[u8], at: usize, little_endian: bool) -> Option<u16> {
+    let b: [u8; 2] = data.get(at..at + 2)?.try_into().ok()?;
+    Some(if little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+}
+
+pub(crate) fn read_u32(data: &[u8], at: usize, little_endian: bool) -> Option<u32> {
+    let b: [u8; 4] = data.get(at..at + 4)?.try_into().ok()?;
+    Some(if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian TIFF with an RGB IFD0 naming the camera and one SubIFD with the
+    /// given PhotometricInterpretation, like a NEF or ARW file
+    fn sample(sub_photometric: u16) -> Vec<u8> {
+        let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            [tag.to_le_bytes().as_slice(), &kind.to_le_bytes(), &count.to_le_bytes(), &value.to_le_bytes()].concat()
+        };
+        // IFD0 at 8 (42 bytes), the SubIFD at 50 (18 bytes), then the Make string
+        let mut data = [b"II*\0".as_slice(), &8u32.to_le_bytes(), &3u16.to_le_bytes()].concat();
+        data.extend(entry(TAG_PHOTOMETRIC, 3, 1, 2));
+        data.extend(entry(0x010F, 2, 6, 68));
+        data.extend(entry(TAG_SUB_IFDS, 4, 1, 50));
+        data.extend(0u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(entry(TAG_PHOTOMETRIC, 3, 1, sub_photometric as u32));
+        data.extend(0u32.to_le_bytes());
+        data.extend(b"NIKON\0");
+        data
+    }
+
+    #[test]
+    fn is_raw_detects_sensor_data_whatever_the_extension() {
+        let tif = Path::new("photo.tif");
+        assert!(is_raw(tif, &sample(PHOTOMETRIC_CFA)));
+        assert!(is_raw(tif, &sample(PHOTOMETRIC_LINEAR_RAW)));
+        assert!(!is_raw(tif, &sample(2)));
+        assert!(is_raw(Path::new("photo.NEF"), &sample(2)));
+        let tiff = Tiff::parse(&sample(2)).unwrap();
+        let groups: Vec<&str> = tiff.ifds.iter().map(|ifd| ifd.group.as_str()).collect();
+        assert_eq!(groups, ["Image", "SubImage1"]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+use std::ops::Range;
+use super::jpeg::Jpeg;
+
+/// Metadata key under which trailing data is reported
+pub const TRAILER_KEY: &str = "Trailing Data";
+
+/// Marker written by Samsung before the video of a motion photo
+const SAMSUNG_MOTION_PHOTO: &[u8] = b"MotionPhoto_Data";
+
+/// Kinds of payloads found after the end of an image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PayloadKind {
+    /// MP4/MOV video, e.g. the clip of a Pixel or Samsung motion photo
+    Mp4,
+    Zip,
+    Jpeg,
+    /// Samsung trailer (SEF), which also wraps motion photo videos
+    Samsung,
+    Unknown,
+}
+
+impl fmt::Display for PayloadKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PayloadKind::Mp4 => "MP4 video",
+            PayloadKind::Zip => "ZIP archive",
+            PayloadKind::Jpeg => "JPEG image",
+            PayloadKind::Samsung => "Samsung trailer",
+            PayloadKind::Unknown => "unknown data",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Bytes that follow the logical end of an image
+pub struct Trailer {
+    /// Offset of the first byte after the image
+    pub offset: usize,
+    pub len: usize,
+    /// Identified payloads, with ranges relative to `offset`
+    pub payloads: Vec<(PayloadKind, Range<usize>)>,
+}
+
+impl Trailer {
+    pub fn new(offset: usize, bytes: &[u8]) -> Self {
+        Trailer { offset, len: bytes.len(), payloads: identify(bytes) }
+    }
+
+    /// Short description for display, e.g. `1843200 bytes: JPEG image (20480 bytes), MP4 video (1822720 bytes)`
+    pub fn describe(&self) -> String {
+        let payloads: Vec<String> =
+            self.payloads.iter().map(|(kind, range)| format!("{} ({} bytes)", kind, range.len())).collect();
+        format!("{} bytes: {}", self.len, payloads.join(", "))
+    }
+}
+
+/// Split trailing bytes into the payloads they are made of
+fn identify(bytes: &[u8]) -> Vec<(PayloadKind, Range<usize>)> {
+    let samsung_start = samsung_start(bytes);
+    let mut payloads = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if samsung_start == Some(pos) {
+            payloads.push((PayloadKind::Samsung, pos..bytes.len()));
+            break;
+        }
+        // Don't let a payload run into the Samsung trailer
+        let end = samsung_start.filter(|&s| s > pos).unwrap_or(bytes.len());
+        let rest = &bytes[pos..end];
+        let (kind, len) = if rest.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            (PayloadKind::Jpeg, Jpeg::parse(rest).map(|jpeg| jpeg.end).unwrap_or(rest.len()))
+        } else if rest.get(4..8) == Some(b"ftyp") {
+            (PayloadKind::Mp4, mp4_len(rest))
+        } else if rest.starts_with(b"PK\x03\x04") {
+            (PayloadKind::Zip, rest.len())
+        } else if rest.starts_with(SAMSUNG_MOTION_PHOTO) {
+            (PayloadKind::Samsung, SAMSUNG_MOTION_PHOTO.len())
+        } else {
+            (PayloadKind::Unknown, rest.len())
+        };
+        let len = len.max(1);
+        payloads.push((kind, pos..pos + len));
+        pos += len;
+    }
+    payloads
+}
+
+/// Length of an MP4 file, following its top-level boxes
+fn mp4_len(data: &[u8]) -> usize {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as u64;
+        let size = match size {
+            0 => (data.len() - pos) as u64,
+            1 if pos + 16 <= data.len() => u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap()),
+            _ => size,
+        };
+        match usize::try_from(size).ok().filter(|&s| s >= 8 && pos + s <= data.len()) {
+            Some(size) => pos += size,
+            None => break,
+        }
+    }
+    pos.max(8).min(data.len())
+}
+
+/// Start of a Samsung SEF trailer, which ends with its length and `SEFT`
+fn samsung_start(bytes: &[u8]) -> Option<usize> {
+    let tail = bytes.len().checked_sub(8)?;
+    if &bytes[tail + 4..] != b"SEFT" {
+        return None;
+    }
+    let len = u32::from_le_bytes(bytes[tail..tail + 4].try_into().unwrap()) as usize;
+    let start = tail.checked_sub(len)?;
+    (bytes.get(start..start + 4) == Some(b"SEFH")).then_some(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::jpeg::{self, tests::sample_jpeg};
+    use crate::policy::RemovalPolicy;
+
+    /// An MP4 file: an ftyp box and a small mdat box
+    fn mp4() -> Vec<u8> {
+        [&16u32.to_be_bytes()[..], b"ftypisom", &[0; 4], &12u32.to_be_bytes(), b"mdat", b"clip"].concat()
+    }
+
+    /// A Samsung SEF trailer: its header, some records, then its length and `SEFT`
+    fn sef() -> Vec<u8> {
+        let body = [b"SEFH".as_slice(), &[0; 12]].concat();
+        [&body[..], &(body.len() as u32).to_le_bytes(), b"SEFT"].concat()
+    }
+
+    fn kinds(payloads: &[(PayloadKind, Range<usize>)]) -> Vec<PayloadKind> {
+        payloads.iter().map(|(kind, _)| *kind).collect()
+    }
+
+    #[test]
+    fn identify_splits_a_samsung_motion_photo() {
+        let preview = sample_jpeg();
+        let bytes = [&preview[..], SAMSUNG_MOTION_PHOTO, &mp4(), &sef()].concat();
+        let payloads = identify(&bytes);
+        assert_eq!(
+            kinds(&payloads),
+            [PayloadKind::Jpeg, PayloadKind::Samsung, PayloadKind::Mp4, PayloadKind::Samsung]
+        );
+        let video_at = preview.len() + SAMSUNG_MOTION_PHOTO.len();
+        assert_eq!(payloads[0].1, 0..preview.len());
+        assert_eq!(payloads[2].1, video_at..video_at + mp4().len());
+        assert_eq!(payloads[3].1, video_at + mp4().len()..bytes.len());
+    }
+
+    #[test]
+    fn identify_reports_archives_and_unknown_data() {
+        let zip = b"PK\x03\x04archive".as_slice();
+        let payloads = identify(&[&mp4()[..], zip].concat());
+        assert_eq!(kinds(&payloads), [PayloadKind::Mp4, PayloadKind::Zip]);
+        assert_eq!(payloads[1].1, mp4().len()..mp4().len() + zip.len());
+        assert_eq!(identify(b"padding"), [(PayloadKind::Unknown, 0..7)]);
+        // A truncated video still ends the payload list at the end of the data
+        let truncated = &mp4()[..20];
+        assert_eq!(identify(truncated), [(PayloadKind::Mp4, 0..16), (PayloadKind::Unknown, 16..20)]);
+        let trailer = Trailer::new(100, &[&mp4()[..], zip].concat());
+        assert_eq!(trailer.describe(), "39 bytes: MP4 video (28 bytes), ZIP archive (11 bytes)");
+    }
+
+    #[test]
+    fn clean_drops_the_trailer_unless_kept() {
+        let image = sample_jpeg();
+        let trailer = [&mp4()[..], &sef()].concat();
+        let data = [&image[..], &trailer].concat();
+        assert_eq!(jpeg::Jpeg::parse(&data).unwrap().trailer(&data), trailer);
+
+        let policy = RemovalPolicy::new(None, &[], &[]).unwrap();
+        let cleaned = jpeg::clean(&data, &policy).unwrap();
+        assert!(jpeg::Jpeg::parse(&cleaned).unwrap().trailer(&cleaned).is_empty());
+        assert!(cleaned.ends_with(&[0xFF, 0xD9]));
+
+        let cleaned = jpeg::clean(&data, &policy.with_trailer(true)).unwrap();
+        assert_eq!(jpeg::Jpeg::parse(&cleaned).unwrap().trailer(&cleaned), trailer);
+    }
+}
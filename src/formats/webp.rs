@@ -0,0 +1,261 @@
+use anyhow::{bail, Result};
+use super::icc::{self, Rewrite};
+use super::{xmp, Container};
+use crate::policy::RemovalPolicy;
+
+pub const RIFF: &[u8; 4] = b"RIFF";
+pub const WEBP: &[u8; 4] = b"WEBP";
+
+/// VP8X feature flags
+const FLAG_ICC: u8 = 0x20;
+const FLAG_EXIF: u8 = 0x08;
+const FLAG_XMP: u8 = 0x04;
+
+/// A chunk of a WebP RIFF container
+pub struct Chunk<'a> {
+    pub fourcc: [u8; 4],
+    pub data: &'a [u8],
+    /// Raw bytes of the whole chunk (header, data and padding byte)
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    /// Metadata container carried by this chunk, if any
+    pub fn container(&self) -> Option<Container> {
+        match &self.fourcc {
+            b"EXIF" => Some(Container::Exif),
+            b"XMP " => Some(Container::Xmp),
+            b"ICCP" => Some(Container::Icc),
+            _ => None,
+        }
+    }
+
+    /// EXIF payload as a raw TIFF block. Some writers prepend the JPEG `Exif\0\0` header.
+    pub fn exif_tiff(&self) -> &'a [u8] {
+        self.data.strip_prefix(super::jpeg::EXIF_HEADER).unwrap_or(self.data)
+    }
+}
+
+/// A parsed WebP file: its chunks and the offset where the RIFF container ends
+pub struct Webp<'a> {
+    pub chunks: Vec<Chunk<'a>>,
+    /// Offset just past the RIFF container; anything after it is trailing data
+    pub end: usize,
+}
+
+impl<'a> Webp<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if data.len() < 12 || &data[..4] != RIFF || &data[8..12] != WEBP {
+            bail!("Not a WebP file");
+        }
+        let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let end = (8 + riff_size).min(data.len());
+        let mut chunks = Vec::new();
+        let mut pos = 12;
+        while pos + 8 <= end {
+            let fourcc = [data[pos], data[pos + 1], data[pos + 2], data[pos + 3]];
+            let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+            let data_end = pos + 8 + size;
+            if data_end > end {
+                bail!("Invalid WebP chunk length at offset {}", pos);
+            }
+            let chunk_end = (data_end + size % 2).min(end);
+            chunks.push(Chunk { fourcc, data: &data[pos + 8..data_end], bytes: &data[pos..chunk_end] });
+            pos = chunk_end;
+        }
+        Ok(Webp { chunks, end })
+    }
+
+    /// Bytes after the RIFF container
+    pub fn trailer(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.end..]
+    }
+}
+
+/// Detect the metadata containers in a WebP file
+pub fn containers(data: &[u8]) -> Result<Vec<Container>> {
+    let webp = Webp::parse(data)?;
+    let mut found = Vec::new();
+    for chunk in &webp.chunks {
+        if let Some(container) = chunk.container() {
+            found.push(container);
+            if container == Container::Exif && super::exif_has_thumbnail(chunk.exif_tiff()) {
+                found.push(Container::Thumbnail);
+            }
+        }
+    }
+    if !webp.trailer(data).is_empty() {
+        found.push(Container::Trailer);
+    }
+    Ok(found)
+}
+
+/// Raw TIFF block of the EXIF chunk, if any
+pub fn exif_tiff(data: &[u8]) -> Option<Vec<u8>> {
+    let webp = Webp::parse(data).ok()?;
+    let chunk = webp.chunks.iter().find(|c| &c.fourcc == b"EXIF")?;
+    Some(chunk.exif_tiff().to_vec())
+}
+
+/// ICC profile of the ICCP chunk, if any
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let webp = Webp::parse(data).ok()?;
+    webp.chunks.iter().find(|c| &c.fourcc == b"ICCP").map(|c| c.data.to_vec())
+}
+
+/// Rewrite a WebP with the EXIF and XMP chunks reduced to the tags the policy keeps,
+/// dropping the chunks left empty. The ICC profile is kept, stripped or replaced following
+/// the policy's ICC mode; every other chunk (ANIM, ANMF, ALPH, image data, ...) is copied as
+/// it is. The VP8X flags and the RIFF size are updated to match. Data after the RIFF container is dropped unless
+/// the policy keeps it.
+pub fn clean(data: &[u8], policy: &RemovalPolicy) -> Result<Vec<u8>> {
+    let webp = Webp::parse(data)?;
+    let mut body = Vec::with_capacity(data.len());
+    let mut vp8x_flags_at = None;
+    let mut flags_cleared = 0u8;
+    for chunk in &webp.chunks {
+        match &chunk.fourcc {
+            b"VP8X" if !chunk.data.is_empty() => {
+                vp8x_flags_at = Some(body.len() + 8);
+                body.extend_from_slice(chunk.bytes);
+            }
+            b"EXIF" => {
+                let kept = if policy.removes_everything() {
+                    None
+                } else {
+                    super::filter_exif(chunk.exif_tiff(), policy)?
+                };
+                match kept {
+                    Some(tiff) => write_chunk(&mut body, b"EXIF", &tiff)?,
+                    None => flags_cleared |= FLAG_EXIF,
+                }
+            }
+            b"XMP " => {
+                let kept = if policy.removes_everything() {
+                    None
+                } else {
+                    xmp::filter(chunk.data, policy)?
+                };
+                match kept {
+                    Some(packet) => write_chunk(&mut body, b"XMP ", &packet)?,
+                    None => flags_cleared |= FLAG_XMP,
+                }
+            }
+            b"ICCP" => match icc::rewrite(chunk.data, policy.icc_mode()) {
+                Rewrite::Keep => body.extend_from_slice(chunk.bytes),
+                Rewrite::Strip => flags_cleared |= FLAG_ICC,
+                Rewrite::Replace(profile) => write_chunk(&mut body, b"ICCP", &profile)?,
+            },
+            _ => body.extend_from_slice(chunk.bytes),
+        }
+    }
+    if let Some(at) = vp8x_flags_at {
+        body[at] &= !flags_cleared;
+    }
+    let riff_size = u32::try_from(body.len() + 4)
+        .map_err(|_| anyhow::anyhow!("WebP file too large"))?;
+    let mut out = Vec::with_capacity(body.len() + 12);
+    out.extend_from_slice(RIFF);
+    out.extend_from_slice(&riff_size.to_le_bytes());
+    out.extend_from_slice(WEBP);
+    out.extend_from_slice(&body);
+    if policy.keeps_trailer() {
+        out.extend_from_slice(webp.trailer(data));
+    }
+    Ok(out)
+}
+
+/// Append a chunk with the given data, padded to an even size
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) -> Result<()> {
+    let size = u32::try_from(data.len())
+        .map_err(|_| anyhow::anyhow!("WebP chunk too large ({} bytes)", data.len()))?;
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{IccMode, PrivacyLevel};
+
+    /// An animated VP8X file with an odd-sized ICC profile, EXIF, XMP, an ANIM chunk and an
+    /// ANMF frame holding ALPH and VP8L data, followed by trailing data
+    fn sample_webp() -> Vec<u8> {
+        let flags = FLAG_ICC | FLAG_EXIF | FLAG_XMP | 0x10 | 0x02;
+        let frame = [&[0u8; 16][..], b"ALPH", &3u32.to_le_bytes(), &[0, 1, 2, 0], b"VP8L", &1u32.to_le_bytes(), &[0x2f, 0]].concat();
+        let mut body = WEBP.to_vec();
+        write_chunk(&mut body, b"VP8X", &[flags, 0, 0, 0, 7, 0, 0, 7, 0, 0]).unwrap();
+        write_chunk(&mut body, b"ICCP", b"device profile!").unwrap();
+        write_chunk(&mut body, b"ANIM", &[0, 0, 0, 0, 0, 0]).unwrap();
+        write_chunk(&mut body, b"ANMF", &frame).unwrap();
+        write_chunk(&mut body, b"EXIF", &super::super::jpeg::tests::sample_exif()).unwrap();
+        write_chunk(&mut body, b"XMP ", xmp::tests::PACKET).unwrap();
+        [RIFF, &(body.len() as u32).to_le_bytes()[..], &body, b"trailer"].concat()
+    }
+
+    fn fourccs(webp: &Webp) -> Vec<&'static str> {
+        webp.chunks.iter().map(|c| match &c.fourcc {
+            b"VP8X" => "VP8X",
+            b"ICCP" => "ICCP",
+            b"ANIM" => "ANIM",
+            b"ANMF" => "ANMF",
+            b"EXIF" => "EXIF",
+            b"XMP " => "XMP ",
+            _ => "?",
+        }).collect()
+    }
+
+    /// Parse a cleaned file, checking that the RIFF size covers exactly the chunks
+    fn parse(cleaned: &[u8]) -> (Webp<'_>, u8) {
+        let riff_size = u32::from_le_bytes(cleaned[4..8].try_into().unwrap()) as usize;
+        assert_eq!(riff_size + 8, cleaned.len());
+        let webp = Webp::parse(cleaned).unwrap();
+        assert_eq!(webp.end, cleaned.len());
+        let flags = webp.chunks[0].data[0];
+        (webp, flags)
+    }
+
+    #[test]
+    fn clean_keeps_the_fields_the_policy_keeps() {
+        let original = sample_webp();
+        let strip_gps = RemovalPolicy::new(None, &[], &["GPS*".to_string()]).unwrap();
+        let cleaned = clean(&original, &strip_gps).unwrap();
+        let (webp, flags) = parse(&cleaned);
+        assert_eq!(fourccs(&webp), ["VP8X", "ICCP", "ANIM", "ANMF", "EXIF", "XMP "]);
+        assert_eq!(flags, original[20]);
+        let original_webp = Webp::parse(&original).unwrap();
+        assert_eq!(webp.chunks[3].bytes, original_webp.chunks[3].bytes);
+        let packet = String::from_utf8(webp.chunks[5].data.to_vec()).unwrap();
+        assert!(!packet.contains("GPSLatitude") && packet.contains("dc:creator"));
+        let exif_data = exif::Reader::new().read_raw(webp.chunks[4].exif_tiff().to_vec()).unwrap();
+        assert!(exif_data.fields().all(|f| f.tag != exif::Tag::GPSLatitudeRef));
+    }
+
+    #[test]
+    fn clean_clears_the_flags_of_removed_chunks() {
+        let original = sample_webp();
+        // Only orientation fields are left of EXIF and XMP
+        let default = RemovalPolicy::new(None, &[], &[]).unwrap();
+        let cleaned = clean(&original, &default).unwrap();
+        let (webp, flags) = parse(&cleaned);
+        assert_eq!(fourccs(&webp), ["VP8X", "ICCP", "ANIM", "ANMF", "EXIF", "XMP "]);
+        assert_eq!(flags, original[20]);
+
+        let all = RemovalPolicy::new(Some(PrivacyLevel::All), &[], &[]).unwrap().with_icc(Some(IccMode::Keep));
+        let cleaned = clean(&original, &all).unwrap();
+        let (webp, flags) = parse(&cleaned);
+        assert_eq!(fourccs(&webp), ["VP8X", "ICCP", "ANIM", "ANMF"]);
+        assert_eq!(flags, FLAG_ICC | 0x10 | 0x02);
+
+        let all = RemovalPolicy::new(Some(PrivacyLevel::All), &[], &[]).unwrap();
+        let cleaned = clean(&original, &all).unwrap();
+        let (webp, flags) = parse(&cleaned);
+        assert_eq!(fourccs(&webp), ["VP8X", "ANIM", "ANMF"]);
+        assert_eq!(flags, 0x10 | 0x02);
+    }
+}
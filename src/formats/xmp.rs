@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+use anyhow::{bail, Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::{Namespace, Prefix, ResolveResult};
+use quick_xml::{NsReader, Writer};
+use crate::policy::RemovalPolicy;
+
+const RDF_NS: &[u8] = b"http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+/// Start of the processing instruction closing a packet
+const PACKET_END: &[u8] = b"<?xpacket end";
+
+/// Prefixes exiv2 uses in its keys for common namespaces, whatever prefix a packet declares
+const PREFIXES: [(&str, &str); 12] = [
+    ("http://purl.org/dc/elements/1.1/", "dc"),
+    ("http://ns.adobe.com/xap/1.0/", "xmp"),
+    ("http://ns.adobe.com/xap/1.0/rights/", "xmpRights"),
+    ("http://ns.adobe.com/xap/1.0/mm/", "xmpMM"),
+    ("http://ns.adobe.com/photoshop/1.0/", "photoshop"),
+    ("http://ns.adobe.com/exif/1.0/", "exif"),
+    ("http://ns.adobe.com/exif/1.0/aux/", "aux"),
+    ("http://ns.adobe.com/tiff/1.0/", "tiff"),
+    ("http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/", "iptc"),
+    ("http://iptc.org/std/Iptc4xmpExt/2008-02-29/", "iptcExt"),
+    ("http://ns.adobe.com/camera-raw-settings/1.0/", "crs"),
+    ("http://ns.adobe.com/lightroom/1.0/", "lr"),
+];
+
+/// Where an element sits in the RDF tree
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Rdf,
+    Description,
+    Property,
+    Other,
+}
+
+/// Counts of the top-level properties of a packet
+#[derive(Default)]
+struct Counts {
+    kept: usize,
+    removed: usize,
+}
+
+/// Remove the properties selected by `policy` from an XMP packet. Top-level properties are
+/// decided as a whole by their exiv2 key (`Xmp.dc.creator`), arrays and structures included,
+/// whether they are written as elements or as attributes of `rdf:Description`.
+///
+/// Returns the packet as it is when nothing is removed, and `None` when no property is left.
+pub fn filter<'a>(packet: &'a [u8], policy: &RemovalPolicy) -> Result<Option<Cow<'a, [u8]>>> {
+    let mut reader = NsReader::from_reader(packet);
+    let mut writer = Writer::new(Vec::with_capacity(packet.len()));
+    let mut stack: Vec<Node> = Vec::new();
+    let mut counts = Counts::default();
+    // Depth inside a removed property element, whose events are not copied
+    let mut skipping = 0;
+    loop {
+        let event = reader.read_event().context("Invalid XMP packet")?;
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                Event::Eof => bail!("Invalid XMP packet: unexpected end"),
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Eof => break,
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let is_start = matches!(event, Event::Start(_));
+                let (ns, local) = reader.resolve_element(e.name());
+                let node = match (stack.last(), is_rdf(&ns), local.as_ref()) {
+                    (_, true, b"RDF") => Node::Rdf,
+                    (Some(Node::Rdf), true, b"Description") => Node::Description,
+                    (Some(Node::Description), false, _) => Node::Property,
+                    _ => Node::Other,
+                };
+                let element = match node {
+                    Node::Property if policy.should_remove(&key(&ns, e.name().prefix(), local.as_ref())) => {
+                        counts.removed += 1;
+                        if is_start {
+                            skipping = 1;
+                        }
+                        continue;
+                    }
+                    Node::Property => {
+                        counts.kept += 1;
+                        e.to_owned()
+                    }
+                    Node::Description => filter_attributes(&reader, e, policy, &mut counts)?,
+                    Node::Rdf | Node::Other => e.to_owned(),
+                };
+                if is_start {
+                    stack.push(node);
+                    writer.write_event(Event::Start(element))?;
+                } else {
+                    writer.write_event(Event::Empty(element))?;
+                }
+            }
+            Event::End(_) => {
+                stack.pop();
+                writer.write_event(event)?;
+            }
+            other => writer.write_event(other)?,
+        }
+    }
+    Ok(match counts {
+        Counts { removed: 0, .. } => Some(Cow::Borrowed(packet)),
+        Counts { kept: 0, .. } => None,
+        _ => Some(Cow::Owned(writer.into_inner())),
+    })
+}
+
+/// Pad a packet with spaces to `len` bytes, before the packet trailer when there is one,
+/// so it can take the place of a packet of that length. `None` if it is longer.
+pub fn pad(packet: &[u8], len: usize) -> Option<Vec<u8>> {
+    let padding = len.checked_sub(packet.len())?;
+    let at = packet
+        .windows(PACKET_END.len())
+        .rposition(|w| w == PACKET_END)
+        .unwrap_or(packet.len());
+    Some([&packet[..at], &vec![b' '; padding], &packet[at..]].concat())
+}
+
+/// Copy of an `rdf:Description` element without the property attributes `policy` removes
+fn filter_attributes(
+    reader: &NsReader<&[u8]>,
+    element: &BytesStart,
+    policy: &RemovalPolicy,
+    counts: &mut Counts,
+) -> Result<BytesStart<'static>> {
+    let mut filtered = element.to_owned();
+    filtered.clear_attributes();
+    for attr in element.attributes() {
+        let attr = attr.context("Invalid XMP attribute")?;
+        let (ns, local) = reader.resolve_attribute(attr.key);
+        // Namespace declarations, rdf:about and unqualified attributes are not properties
+        let property = attr.key.as_namespace_binding().is_none() && matches!(ns, ResolveResult::Bound(_)) && !is_rdf(&ns);
+        if property {
+            if policy.should_remove(&key(&ns, attr.key.prefix(), local.as_ref())) {
+                counts.removed += 1;
+                continue;
+            }
+            counts.kept += 1;
+        }
+        filtered.push_attribute(attr);
+    }
+    Ok(filtered)
+}
+
+fn is_rdf(ns: &ResolveResult) -> bool {
+    matches!(ns, ResolveResult::Bound(Namespace(uri)) if *uri == RDF_NS)
+}
+
+/// exiv2 key of a property: `Xmp.<prefix>.<name>`, with exiv2's prefix for known namespaces
+fn key(ns: &ResolveResult, prefix: Option<Prefix>, local: &[u8]) -> String {
+    let known = match ns {
+        ResolveResult::Bound(Namespace(uri)) => PREFIXES.iter().find(|(known, _)| known.as_bytes() == *uri),
+        _ => None,
+    };
+    let prefix = match (known, prefix) {
+        (Some((_, known)), _) => Cow::Borrowed(*known),
+        (None, Some(prefix)) => String::from_utf8_lossy(prefix.into_inner()),
+        (None, None) => Cow::Borrowed(""),
+    };
+    format!("Xmp.{}.{}", prefix, String::from_utf8_lossy(local))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::policy::PrivacyLevel;
+
+    /// A packet with GPS, rights and orientation properties, as elements and as attributes
+    pub(crate) const PACKET: &[u8] = br#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:exif="http://ns.adobe.com/exif/1.0/" xmlns:tiff="http://ns.adobe.com/tiff/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/" exif:GPSLatitude="48,51.5N" tiff:Orientation="6">
+   <dc:rights><rdf:Alt><rdf:li xml:lang="x-default">Jane Doe</rdf:li></rdf:Alt></dc:rights>
+   <dc:creator><rdf:Seq><rdf:li>Jane Doe</rdf:li></rdf:Seq></dc:creator>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    fn filtered(policy: &RemovalPolicy) -> Option<String> {
+        filter(PACKET, policy).unwrap().map(|p| String::from_utf8(p.into_owned()).unwrap())
+    }
+
+    #[test]
+    fn filter_removes_only_the_selected_properties() {
+        let strip_gps = RemovalPolicy::new(None, &[], &["GPS*".to_string()]).unwrap();
+        let packet = filtered(&strip_gps).unwrap();
+        assert!(!packet.contains("GPSLatitude"));
+        assert!(packet.contains("tiff:Orientation=\"6\"") && packet.contains("Jane Doe</rdf:li></rdf:Alt></dc:rights>"));
+
+        let red = RemovalPolicy::new(Some(PrivacyLevel::Red), &[], &[]).unwrap();
+        let packet = filtered(&red).unwrap();
+        assert!(!packet.contains("GPSLatitude") && !packet.contains("dc:creator"));
+        assert!(packet.contains("dc:rights"));
+
+        let keep_all = RemovalPolicy::new(None, &[], &["Nothing".to_string()]).unwrap();
+        assert!(matches!(filter(PACKET, &keep_all).unwrap(), Some(Cow::Borrowed(_))));
+        let all = RemovalPolicy::new(Some(PrivacyLevel::All), &[], &[]).unwrap();
+        assert_eq!(filtered(&all), None);
+    }
+
+    #[test]
+    fn pad_keeps_the_packet_trailer_last() {
+        let padded = pad(b"<x/><?xpacket end=\"w\"?>", 30).unwrap();
+        assert_eq!(padded, b"<x/>       <?xpacket end=\"w\"?>");
+        assert_eq!(pad(PACKET, 10), None);
+    }
+}
@@ -0,0 +1,9 @@
+//! Metadata model of medars, shared by the `medars` binary and by downstream code that
+//! reads its reports back: `serde_json::from_str::<medars::report::Report>(&output)` parses
+//! the output of `medars show --format json`.
+
+pub mod entry;
+pub mod formats;
+pub mod policy;
+pub mod report;
+pub mod sensitivity;
@@ -4,13 +4,17 @@ use std::fs::{OpenOptions, create_dir_all, File};
 use std::io::{BufReader, BufRead, Write};
 use std::path::PathBuf;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LogEntry {
     pub timestamp: DateTime<Utc>,
     pub action: String,
     pub file: String,
     pub result: String,
     pub details: Option<String>,
+    /// Content hash of the original in the backup store, when `clean --backup` overwrote it.
+    /// The file path of such entries is absolute, so `undo` works from any directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
 }
 
 pub struct Logger {
@@ -1,11 +1,27 @@
 use clap::{Parser, Subcommand};
+use std::io::IsTerminal;
 use std::path::PathBuf;
+mod backup;
+use backup::{BackupStore, UndoSelection};
+mod batch;
+use batch::Progress;
+mod atomic;
+mod attrs;
+use attrs::{AttrArgs, XattrMode};
+use medars::{entry, formats, policy, report, sensitivity};
 mod metadata;
 use metadata::MetadataHandler;
+use report::{OutputFormat, Summary};
+use policy::PolicyArgs;
+use sensitivity::Sensitivity;
 mod ui;
 use ui::RatatuiUI;
 mod logger;
 use logger::{Logger, LogEntry};
+mod stdio;
+mod verify;
+mod walk;
+use walk::WalkOptions;
 
 #[derive(Parser)]
 #[command(name = "medars")]
@@ -25,18 +41,22 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Check if an image contains metadata
+    /// Check if images contain metadata (exits with status 1 if any is found, 2 if a file could not be checked)
     Check {
-        #[arg(value_name = "FILE")]
-        file: PathBuf,
+        #[arg(value_name = "FILES", required = true, help = "Image files to check (supports patterns, e.g. *.jpg)")]
+        files: Vec<String>,
+        #[command(flatten)]
+        walk: WalkOptions,
     },
     /// View metadata in a readable format
     Show {
-        #[arg(value_name = "FILE")]
-        file: PathBuf,
-        /// Output format (json, table)
-        #[arg(short, long, default_value = "table")]
-        format: String,
+        #[arg(value_name = "FILES", required = true, help = "Image files to show (supports patterns, e.g. *.jpg)")]
+        files: Vec<String>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        #[command(flatten)]
+        walk: WalkOptions,
     },
     /// Clean metadata from one or more images (supports batch mode and glob patterns)
     ///
@@ -45,18 +65,73 @@ enum Commands {
     ///   medars clean image.jpg
     /// 
     ///   medars clean *.jpg --copy
+    /// 
+    ///   medars clean image.jpg --strip 'GPS*,Make,Model'
+    /// 
+    ///   medars clean image.jpg --level red
+    /// 
+    ///   medars clean photos/ --recursive --exclude 'drafts'
     Clean {
         #[arg(value_name = "FILES", required = true, help = "Image files to clean (supports patterns, e.g. *.jpg for batch mode)")]
         files: Vec<String>,
         /// Output file path (if not specified, overwrites original; only valid for single file)
         #[arg(short, long)]
         output: Option<PathBuf>,
-        /// Copy to new file (optional path, or auto-name if not provided)
+        /// Copy to new file (optional path for a single file, or auto-name if not provided)
         #[arg(long, value_name = "COPY_PATH")]
         copy: Option<Option<PathBuf>>,
         /// Show what would be removed, but do not modify the file
         #[arg(long)]
         dry_run: bool,
+        #[command(flatten)]
+        policy: PolicyArgs,
+        /// Check each cleaned image afterwards: no selected metadata may be left, and its pixels
+        /// must match the original (exits with status 1 otherwise)
+        #[arg(long)]
+        verify: bool,
+        /// Keep a copy of each overwritten original in the backup store, so `medars undo` can restore it
+        #[arg(long)]
+        backup: bool,
+        /// Number of files to clean in parallel (default: one per CPU)
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+        #[command(flatten)]
+        attrs: AttrArgs,
+        #[command(flatten)]
+        walk: WalkOptions,
+    },
+
+    /// Check a cleaned image: none of the metadata selected by the policy options may be left,
+    /// and its pixels must match the original (exits with status 1 otherwise)
+    Verify {
+        /// The image before cleaning
+        #[arg(value_name = "ORIGINAL")]
+        original: PathBuf,
+        /// The cleaned image
+        #[arg(value_name = "CLEANED")]
+        cleaned: PathBuf,
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
+
+    /// Restore originals overwritten by `clean --backup`
+    #[command(group(clap::ArgGroup::new("which").required(true).args(["last", "file", "since"])))]
+    Undo {
+        /// Restore the most recently cleaned file
+        #[arg(long)]
+        last: bool,
+        /// Restore this file as it was before it was last cleaned
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Restore every file cleaned since this time, as it was before (e.g. 2024-05-01 or 2024-05-01T12:00:00Z)
+        #[arg(long, value_name = "TIME", value_parser = backup::parse_time)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+    },
+
+    /// Manage the backups kept by `clean --backup`
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand,
     },
 
     /// Show log entries
@@ -73,6 +148,19 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum BackupsCommand {
+    /// Remove the oldest backups to enforce a size and/or age limit
+    #[command(group(clap::ArgGroup::new("limit").required(true).multiple(true).args(["max_size", "max_age"])))]
+    Prune {
+        /// Maximum total size of the backups, in megabytes
+        #[arg(long, value_name = "MB")]
+        max_size: Option<u64>,
+        /// Maximum age of a backup, in days
+        #[arg(long, value_name = "DAYS")]
+        max_age: Option<u64>,
+    },
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -90,42 +178,83 @@ async fn main() -> anyhow::Result<()> {
         }
 
         match command {
-            Commands::Check { file } => {
+            Commands::Check { files, walk } => match check(files, walk, cli.quiet) {
+                Ok(false) => {}
+                Ok(true) => std::process::exit(1),
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    eprintln!("Error: {:?}", e);
+                    std::process::exit(CHECK_ERROR);
+                }
+            },
+            Commands::Show { files, format, walk } => {
                 let handler = MetadataHandler::new();
-                let has_metadata = handler.has_metadata(&file)?;
-                if !cli.quiet {
-                    if has_metadata {
-                        log::info!("❌ Image contains metadata");
-                        println!("❌ Image contains metadata");
+                if stdio::reads_stdin(files)? {
+                    let report = handler.report_from_buffer(std::path::Path::new(stdio::STDIO), &stdio::read_stdin()?)?;
+                    return handler.display_reports(&[report], *format, false, cli.quiet);
+                }
+                let all_files = walk::collect_files(files, walk)?;
+                if all_files.is_empty() {
+                    anyhow::bail!("No files matched the given pattern(s).");
+                }
+                let batch = all_files.len() > 1;
+                let mut reports = Vec::new();
+                let mut failed = 0;
+                for file in &all_files {
+                    match handler.report(file) {
+                        Ok(report) => reports.push(report),
+                        Err(e) if batch => {
+                            log::error!("Error: {}: {}", file.display(), e);
+                            eprintln!("Error: {}: {}", file.display(), e);
+                            failed += 1;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                handler.display_reports(&reports, *format, batch, cli.quiet)?;
+                if batch && !cli.quiet {
+                    // Keep the machine-readable formats parseable
+                    if *format == OutputFormat::Table {
+                        println!("{}", Summary::new(&reports));
                     } else {
-                        log::warn!("✅ No metadata found in image");
-                        eprintln!("✅ No metadata found in image");
+                        eprintln!("{}", Summary::new(&reports));
                     }
                 }
-            }
-            Commands::Show { file, format } => {
-                let handler = MetadataHandler::new();
-                if let Err(e) = handler.display_metadata(&file, &format, cli.quiet) {
-                    log::error!("Error: {}", e);
-                    eprintln!("Error: {}", e);
+                if failed > 0 {
+                    anyhow::bail!("{} of {} files could not be read", failed, all_files.len());
                 }
             }
-            Commands::Clean { files, output, copy, dry_run } => {
-                use glob::glob;
+            Commands::Clean { files, output, copy, dry_run, policy, verify: check_output, backup, jobs, attrs, walk } => {
                 let handler = MetadataHandler::new();
-                let mut all_files = Vec::new();
-                for pattern in files {
-                    match glob(pattern) {
-                        Ok(paths) => {
-                            for entry in paths.flatten() {
-                                all_files.push(entry);
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Invalid pattern '{}': {}", pattern, e);
-                        }
+                let policy = policy.policy()?;
+                let from_stdin = stdio::reads_stdin(files)?;
+                if from_stdin || output.as_deref().is_some_and(stdio::is_stdio) {
+                    if let Some(flag) = stdio_conflict(command, from_stdin) {
+                        anyhow::bail!("{} cannot be used when cleaning from standard input or to standard output", flag);
                     }
+                    let (file, data) = if from_stdin {
+                        (PathBuf::from(stdio::STDIO), stdio::read_stdin()?)
+                    } else {
+                        match walk::collect_files(files, walk)?.as_slice() {
+                            [file] => (file.clone(), std::fs::read(file)?),
+                            _ => anyhow::bail!("--output - takes exactly one file"),
+                        }
+                    };
+                    let result = handler.clean_buffer(&data, &policy).and_then(|cleaned| stdio::write_stdout(&cleaned));
+                    logger.log(&LogEntry {
+                        timestamp: chrono::Utc::now(),
+                        action: "clean".to_string(),
+                        file: file.display().to_string(),
+                        result: if result.is_ok() { "success" } else { "failure" }.to_string(),
+                        details: Some(match &result {
+                            Ok(()) => "Saved on: standard output".to_string(),
+                            Err(e) => format!("Error: {}", e),
+                        }),
+                        backup: None,
+                    });
+                    return result;
                 }
+                let all_files = walk::collect_files(files, walk)?;
                 if all_files.is_empty() {
                     eprintln!("No files matched the given pattern(s).");
                     return Ok(());
@@ -133,23 +262,54 @@ async fn main() -> anyhow::Result<()> {
                 let single_output = output.as_ref();
                 let single_copy = copy.as_ref();
                 let is_single = all_files.len() == 1;
-                for file in &all_files {
-                    if *dry_run {
-                        let meta = handler.get_metadata_map(file)?;
-                        if meta.is_empty() {
+                if !is_single && matches!(copy, Some(Some(_))) {
+                    // Every file would be written to the same path, in parallel
+                    anyhow::bail!("--copy with a path takes a single file; use --copy without a path to save each copy next to its original");
+                }
+                if *dry_run {
+                    for file in &all_files {
+                        let mut entries = handler.get_metadata(file)?;
+                        entries.retain(|e| !e.is_file_property() && policy.should_remove(&e.key));
+                        if entries.is_empty() {
                             if !cli.quiet {
                                 println!("✅ No metadata found in image (nothing to remove): {}", file.display());
                             }
-                        } else {
-                            if !cli.quiet {
-                                println!("The following metadata would be removed from {}:", file.display());
-                                for (k, v) in meta.iter() {
-                                    println!("- {}: {}", k, v);
+                        } else if !cli.quiet {
+                            println!("The following metadata would be removed from {}:", file.display());
+                            entries.sort_by(|a, b| a.key.cmp(&b.key));
+                            let groups = [
+                                (Sensitivity::Red, "🔴 Insecure"),
+                                (Sensitivity::Yellow, "🟡 Better to remove"),
+                                (Sensitivity::Green, "🟢 Safe to share"),
+                                (Sensitivity::Unrecognized, "⚪ Unrecognized"),
+                            ];
+                            for (sensitivity, title) in groups {
+                                let group: Vec<_> = entries.iter().filter(|e| e.sensitivity == sensitivity).collect();
+                                if group.is_empty() {
+                                    continue;
+                                }
+                                println!("{} ({}):", title, group.len());
+                                for entry in group {
+                                    println!("- {}: {}", entry.key, entry.display);
                                 }
                             }
                         }
-                        continue;
                     }
+                    return Ok(());
+                }
+                let jobs = jobs.unwrap_or_else(batch::default_jobs);
+                if jobs > 1 {
+                    // gexiv2 has to be set up once before it is used from several threads
+                    rexiv2::initialize().map_err(|e| anyhow::anyhow!("Failed to initialize rexiv2: {}", e))?;
+                }
+                // The bar replaces the per-file lines in a terminal; piped output keeps them
+                let progress = (!cli.quiet && !is_single && std::io::stdout().is_terminal() && std::io::stderr().is_terminal())
+                    .then(|| Progress::new(&all_files));
+                let store = BackupStore::new();
+                let clean_file = |file: &PathBuf| -> anyhow::Result<(PathBuf, Option<String>)> {
+                    // Decode before cleaning, as the original may be overwritten
+                    let original = check_output.then(|| verify::pixel_hash(file));
+                    let captured = attrs.capture(file)?;
                     let output_path = if let Some(copy_flag) = single_copy {
                         // --copy provided: always copy to new file (batch or single)
                         match copy_flag {
@@ -179,53 +339,163 @@ async fn main() -> anyhow::Result<()> {
                     };
                     if let Some(parent) = output_path.parent() {
                         if parent != std::path::Path::new("") && parent != std::path::Path::new(".") && !parent.exists() {
-                            if let Err(e) = std::fs::create_dir_all(parent) {
-                                log::error!("Failed to create output directory {}: {}", parent.display(), e);
-                                eprintln!("Failed to create output directory {}: {}", parent.display(), e);
-                                logger.log(&LogEntry {
-                                    timestamp: chrono::Utc::now(),
-                                    action: "remove".to_string(),
-                                    file: file.display().to_string(),
-                                    result: "failure".to_string(),
-                                    details: Some(format!("Failed to create output directory: {}", e)),
-                                });
-                                continue;
-                            }
+                            std::fs::create_dir_all(parent)
+                                .map_err(|e| anyhow::anyhow!("Failed to create output directory {}: {}", parent.display(), e))?;
                         }
                     }
-                    if single_copy.is_some() {
-                        if output_path != *file {
-                            std::fs::copy(&file, &output_path)?;
-                        }
+                    if single_copy.is_some() && output_path != *file {
+                        std::fs::copy(file, &output_path)?;
                     }
-                    match handler.remove_metadata(&file, &output_path) {
-                        Ok(_) => {
-                            if !cli.quiet {
-                                log::info!("✅ Metadata removed successfully, saved on: {}", output_path.display());
+                    // Verify the temporary file, so an image failing the check never replaces the output
+                    let check = |cleaned: &std::path::Path| match &original {
+                        Some(original) => {
+                            let verification = verify::verify(&handler, &policy, cleaned, original)?;
+                            if !verification.passed() {
+                                anyhow::bail!("Verification of {} failed: {}", output_path.display(), verification);
+                            }
+                            Ok(())
+                        }
+                        None => Ok(()),
+                    };
+                    // Only an overwritten original needs a backup; --copy and --output leave it alone
+                    let backup = if *backup && output_path == *file {
+                        let ((), hash) = store.with_backup(file, || handler.remove_metadata(file, &output_path, &policy, check))?;
+                        Some(hash)
+                    } else {
+                        handler.remove_metadata(file, &output_path, &policy, check)?;
+                        None
+                    };
+                    captured.apply(&output_path)?;
+                    Ok((output_path, backup))
+                };
+                let mut cleaned = 0;
+                let mut failed = 0;
+                batch::run_ordered(
+                    &all_files,
+                    jobs,
+                    |file| {
+                        let bytes = batch::file_size(file);
+                        let result = clean_file(file);
+                        if let Some(progress) = &progress {
+                            progress.file_done(bytes);
+                        }
+                        result
+                    },
+                    |file, result| match result {
+                        Ok((output_path, backup)) => {
+                            cleaned += 1;
+                            log::info!("✅ Metadata removed successfully, saved on: {}", output_path.display());
+                            if !cli.quiet && progress.is_none() {
                                 println!("✅ Metadata removed successfully, saved on: {}", output_path.display());
                             }
+                            // Backed-up files are logged by absolute path, for undo
+                            let logged = match backup {
+                                Some(_) => std::path::absolute(file).unwrap_or_else(|_| file.clone()),
+                                None => file.clone(),
+                            };
                             logger.log(&LogEntry {
                                 timestamp: chrono::Utc::now(),
                                 action: "clean".to_string(),
-                                file: file.display().to_string(),
+                                file: logged.display().to_string(),
                                 result: "success".to_string(),
                                 details: Some(format!("Saved on: {}", output_path.display())),
+                                backup,
                             });
                         }
                         Err(e) => {
+                            failed += 1;
+                            log::error!("Failed to remove metadata from {}: {:#}", file.display(), e);
                             if !cli.quiet {
-                                log::error!("Failed to remove metadata: {}", e);
-                                eprintln!("Failed to remove metadata: {}", e);
+                                match &progress {
+                                    Some(progress) => progress.suspend(|| eprintln!("Failed to remove metadata from {}: {:#}", file.display(), e)),
+                                    None => eprintln!("Failed to remove metadata: {:#}", e),
+                                }
                             }
                             logger.log(&LogEntry {
                                 timestamp: chrono::Utc::now(),
                                 action: "clean".to_string(),
                                 file: file.display().to_string(),
                                 result: "failure".to_string(),
-                                details: Some(format!("Error: {}", e)),
+                                details: Some(format!("Error: {:#}", e)),
+                                backup: None,
                             });
                         }
+                    },
+                );
+                if let Some(progress) = progress {
+                    progress.finish();
+                    println!("✅ Metadata removed from {} of {} files", cleaned, all_files.len());
+                }
+                if *check_output && failed > 0 {
+                    std::process::exit(1);
+                }
+            }
+            Commands::Verify { original, cleaned, policy } => {
+                let handler = MetadataHandler::new();
+                let verification = verify::verify(&handler, &policy.policy()?, cleaned, &verify::pixel_hash(original))?;
+                if verification.passed() {
+                    if !cli.quiet {
+                        println!("✅ {}: {}", cleaned.display(), verification);
+                    }
+                } else {
+                    if !cli.quiet {
+                        println!("❌ {}: {}", cleaned.display(), verification);
+                    }
+                    std::process::exit(1);
+                }
+            }
+            Commands::Undo { last, file, since } => {
+                let selection = match (last, file, since) {
+                    (_, Some(file), _) => UndoSelection::File(std::path::absolute(file)?),
+                    (_, _, Some(since)) => UndoSelection::Since(*since),
+                    _ => UndoSelection::Last,
+                };
+                let entries = backup::select_undo(logger.read_logs(None), &selection);
+                if entries.is_empty() {
+                    anyhow::bail!("No backed-up clean found to undo (only `clean --backup` keeps the originals)");
+                }
+                let store = BackupStore::new();
+                let mut failed = false;
+                for entry in entries {
+                    let Some(hash) = entry.backup else {
+                        continue;
+                    };
+                    let file = PathBuf::from(&entry.file);
+                    let result = store.restore(&hash, &file);
+                    match &result {
+                        Ok(()) => {
+                            if !cli.quiet {
+                                println!("↩️  Restored {} as it was before {}", file.display(), entry.timestamp);
+                            }
+                        }
+                        Err(e) => {
+                            failed = true;
+                            log::error!("Failed to restore {}: {}", file.display(), e);
+                            eprintln!("Failed to restore {}: {:#}", file.display(), e);
+                        }
                     }
+                    logger.log(&LogEntry {
+                        timestamp: chrono::Utc::now(),
+                        action: "undo".to_string(),
+                        file: entry.file,
+                        result: if result.is_ok() { "success" } else { "failure" }.to_string(),
+                        details: result.err().map(|e| format!("Error: {:#}", e)),
+                        backup: Some(hash),
+                    });
+                }
+                if failed {
+                    std::process::exit(1);
+                }
+            }
+            Commands::Backups { command: BackupsCommand::Prune { max_size, max_age } } => {
+                let max_size = max_size.map(|mb| mb * 1024 * 1024);
+                let max_age = max_age.map(|days| std::time::Duration::from_secs(days * 24 * 60 * 60));
+                let stats = BackupStore::new().prune(max_size, max_age)?;
+                if !cli.quiet {
+                    println!(
+                        "🗑️  Removed {} backups ({} bytes); {} left ({} bytes)",
+                        stats.removed, stats.removed_bytes, stats.kept, stats.kept_bytes
+                    );
                 }
             }
             Commands::Log { max } => {
@@ -253,3 +523,116 @@ async fn main() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// Exit status of `check` when a file could not be checked (1 means metadata was found)
+const CHECK_ERROR: i32 = 2;
+
+/// Report the metadata containers of each file. Returns whether any file has metadata,
+/// or an error if a file could not be checked.
+fn check(files: &[String], walk: &WalkOptions, quiet: bool) -> anyhow::Result<bool> {
+    let handler = MetadataHandler::new();
+    let all_files = walk::collect_files(files, walk)?;
+    if all_files.is_empty() {
+        anyhow::bail!("No files matched the given pattern(s).");
+    }
+    let batch = all_files.len() > 1;
+    let mut summary = Summary { files: 0, with_metadata: 0, with_gps: 0 };
+    let mut failed = 0;
+    for file in &all_files {
+        let containers = match handler.detect_containers(file) {
+            Ok(containers) => containers,
+            Err(e) if batch => {
+                log::error!("Error: {}: {}", file.display(), e);
+                eprintln!("Error: {}: {}", file.display(), e);
+                failed += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        summary.files += 1;
+        if containers.is_empty() {
+            if !quiet {
+                log::warn!("✅ No metadata found in image");
+                if batch {
+                    println!("✅ {}: no metadata", file.display());
+                } else {
+                    eprintln!("✅ No metadata found in image");
+                }
+            }
+            continue;
+        }
+        summary.with_metadata += 1;
+        if batch && handler.report(file).is_ok_and(|report| report.has_gps()) {
+            summary.with_gps += 1;
+        }
+        let names = containers.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ");
+        if !quiet {
+            log::info!("❌ Image contains metadata: {}", names);
+            if batch {
+                println!("❌ {}: {}", file.display(), names);
+            } else {
+                println!("❌ Image contains metadata: {}", names);
+            }
+            if let Some(trailer) = handler.scan_trailer(file)? {
+                println!("   Trailing data at offset {}: {}", trailer.offset, trailer.describe());
+            }
+        }
+    }
+    if batch && !quiet {
+        println!("{}", summary);
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} files could not be checked", failed, all_files.len());
+    }
+    Ok(summary.with_metadata > 0)
+}
+
+/// The first option given to `clean` that does not apply when the image only goes through
+/// memory, from standard input or to standard output
+fn stdio_conflict(command: &Commands, from_stdin: bool) -> Option<&'static str> {
+    let Commands::Clean { output, copy, dry_run, verify, backup, jobs, attrs, walk, .. } = command else {
+        return None;
+    };
+    let walks = walk.recursive || !walk.include.is_empty() || !walk.exclude.is_empty() || walk.follow_symlinks;
+    let unsupported = [
+        (copy.is_some(), "--copy"),
+        (*dry_run, "--dry-run"),
+        (*verify, "--verify"),
+        (*backup, "--backup"),
+        (jobs.is_some(), "--jobs"),
+        (attrs.preserve_times || attrs.set_times.is_some(), "--preserve-times and --set-times"),
+        // Clean is the default; keeping or stripping attributes needs a file to carry them
+        (attrs.xattrs != XattrMode::Clean, "--xattrs"),
+        (from_stdin && walks, "--recursive, --include, --exclude and --follow-symlinks"),
+        (output.as_deref().is_some_and(|o| !stdio::is_stdio(o)), "--output with a file"),
+    ];
+    unsupported.into_iter().find(|(set, _)| *set).map(|(_, flag)| flag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(args: &[&str]) -> Option<&'static str> {
+        let cli = Cli::try_parse_from([&["medars", "clean"], args].concat()).unwrap();
+        let command = cli.command.unwrap();
+        let Commands::Clean { files, .. } = &command else { unreachable!() };
+        stdio_conflict(&command, stdio::reads_stdin(files).unwrap())
+    }
+
+    #[test]
+    fn file_options_are_rejected_for_standard_input() {
+        assert_eq!(conflict(&["-"]), None);
+        assert_eq!(conflict(&["-", "--level", "red", "--output", "-"]), None);
+        assert_eq!(conflict(&["-", "--recursive"]), Some("--recursive, --include, --exclude and --follow-symlinks"));
+        assert_eq!(conflict(&["-", "--include", "*.jpg"]), Some("--recursive, --include, --exclude and --follow-symlinks"));
+        assert_eq!(conflict(&["-", "--jobs", "4"]), Some("--jobs"));
+        assert_eq!(conflict(&["-", "--backup"]), Some("--backup"));
+        assert_eq!(conflict(&["-", "--set-times", "epoch"]), Some("--preserve-times and --set-times"));
+        assert_eq!(conflict(&["-", "--xattrs", "keep"]), Some("--xattrs"));
+        assert_eq!(conflict(&["-", "--output", "clean.jpg"]), Some("--output with a file"));
+        // A single file written to standard output may still be found by a walk
+        assert_eq!(conflict(&["photo.jpg", "--output", "-", "--exclude", "drafts"]), None);
+        assert_eq!(conflict(&["photo.jpg", "--output", "-", "--jobs", "2"]), Some("--jobs"));
+    }
+}
@@ -1,125 +1,434 @@
-use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+use std::{io::{Cursor, Write}, path::Path, time::SystemTime};
 use anyhow::{Context, Result};
 use exif;
-use crate::ui::image_utils::{RED_KEYS, YELLOW_KEYS, GREEN_KEYS};
+use crate::atomic;
+use crate::entry::{MetadataEntry, Value};
+use crate::formats::{self, Container, ImageFormat};
+use crate::formats::icc::{self, ICC_PREFIX};
+use crate::formats::trailer::{Trailer, TRAILER_KEY};
+use crate::policy::{IccMode, RemovalPolicy};
+use crate::report::{self, OutputFormat, Report, CSV_HEADER};
+use crate::sensitivity::Sensitivity;
+
+/// exiv2 key of the TIFF tag holding the ICC profile
+const ICC_TAG: &str = "Exif.Image.InterColorProfile";
 
 pub struct MetadataHandler;
 
 impl MetadataHandler {
-    /// Get all metadata as a key-value map (for dry-run and inspection)
-    pub fn get_metadata_map(&self, path: &Path) -> Result<HashMap<String, String>> {
+    /// Get all metadata as typed entries (for dry-run and inspection)
+    pub fn get_metadata(&self, path: &Path) -> Result<Vec<MetadataEntry>> {
         self.extract_metadata(path)
     }
+
+    /// Get all metadata of an image held in memory. There is no file, so the only file
+    /// properties are the size and the dimensions.
+    pub fn get_metadata_from_buffer(&self, data: &[u8]) -> Result<Vec<MetadataEntry>> {
+        self.extract_entries(data, None)
+    }
     pub fn new() -> Self {
         Self
     }
     
-    /// Check if an image has any metadata
-    pub fn has_metadata(&self, path: &Path) -> Result<bool> {
+    /// List the metadata containers present in an image (EXIF, XMP, IPTC, ICC, comments, ...)
+    pub fn detect_containers(&self, path: &Path) -> Result<Vec<Container>> {
         if !path.exists() {
             anyhow::bail!("File does not exist: {}", path.display());
         }
-        let file = File::open(path)?;
-        let mut bufreader = BufReader::new(&file);
-        match exif::Reader::new().read_from_container(&mut bufreader) {
-            Ok(exif_data) => Ok(exif_data.fields().count() > 0),
-            Err(_) => self.check_other_metadata(path),
+        self.detect_containers_in_buffer(&std::fs::read(path)?)
+    }
+
+    /// List the metadata containers present in an image held in memory
+    fn detect_containers_in_buffer(&self, data: &[u8]) -> Result<Vec<Container>> {
+        let mut containers = Vec::new();
+        if let Ok(exif_data) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) {
+            if exif_data.fields().count() > 0 {
+                containers.push(Container::Exif);
+            }
+            if exif_data.fields().any(|f| f.ifd_num == exif::In::THUMBNAIL) {
+                containers.push(Container::Thumbnail);
+            }
         }
+        containers.extend(self.check_other_metadata(data));
+        containers.sort();
+        containers.dedup();
+        Ok(containers)
     }
 
-    /// Display metadata in the specified format ("json" or "table")
-    pub fn display_metadata(&self, path: &Path, format: &str, quiet: bool) -> Result<()> {
+    /// Scan for data that follows the logical end of the image (appended archives, motion photo videos, ...)
+    pub fn scan_trailer(&self, path: &Path) -> Result<Option<Trailer>> {
         if !path.exists() {
             anyhow::bail!("File does not exist: {}", path.display());
         }
-        let metadata = self.extract_metadata(path)?;
-        match format.to_lowercase().as_str() {
-            "json" => self.display_json(&metadata, quiet)?,
-            _ => self.display_table(&metadata, quiet)?,
+        Ok(formats::trailer(&std::fs::read(path)?))
+    }
+
+    /// Display the reports of one or more images in the specified format. In a `batch` of
+    /// files, the reports make a JSON or YAML array and get a header each in the table, even
+    /// when only one file could be read. CSV shares one header.
+    pub fn display_reports(&self, reports: &[Report], format: OutputFormat, batch: bool, quiet: bool) -> Result<()> {
+        if quiet {
+            return Ok(());
+        }
+        // The table explains it itself; the other formats stay parseable, so warn on stderr
+        if format != OutputFormat::Table {
+            for report in reports.iter().filter(|r| !r.has_metadata()) {
+                eprintln!("⚠️  No Metadata found in image: {}", report.file.display());
+            }
+        }
+        let mut out = std::io::stdout().lock();
+        match (format, reports) {
+            (OutputFormat::Table, [report]) if !batch => self.display_table(&report.fields, quiet)?,
+            (OutputFormat::Table, _) => {
+                for report in reports {
+                    println!("📄 {}", report.file.display());
+                    self.display_table(&report.fields, quiet)?;
+                }
+            }
+            (OutputFormat::Json, _) => report::write_json(&mut out, reports, batch)?,
+            (OutputFormat::Ndjson, _) => {
+                for report in reports {
+                    report.write_ndjson(&mut out)?;
+                }
+            }
+            (OutputFormat::Csv, _) => {
+                let mut writer = csv::Writer::from_writer(out);
+                writer.write_record(CSV_HEADER)?;
+                for report in reports {
+                    report.write_csv(&mut writer)?;
+                }
+                writer.flush()?;
+            }
+            (OutputFormat::Yaml, _) => report::write_yaml(&mut out, reports, batch)?,
+            (OutputFormat::Markdown, _) => {
+                for (i, report) in reports.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(out)?;
+                    }
+                    report.write_markdown(&mut out)?;
+                }
+            }
         }
         Ok(())
     }
 
-    /// Remove all metadata from an image and save to output_path
-    pub fn remove_metadata(&self, input_path: &Path, output_path: &Path) -> Result<()> {
+    /// Build the schema-versioned report of an image (file, detected format and fields)
+    pub fn report(&self, path: &Path) -> Result<Report> {
+        if !path.exists() {
+            anyhow::bail!("File does not exist: {}", path.display());
+        }
+        let format = ImageFormat::detect(&std::fs::read(path)?);
+        Ok(Report::new(path, format, self.extract_metadata(path)?))
+    }
+
+    /// Build the report of an image held in memory; `name` stands in for its path (`-` for stdin)
+    pub fn report_from_buffer(&self, name: &Path, data: &[u8]) -> Result<Report> {
+        Ok(Report::new(name, ImageFormat::detect(data), self.get_metadata_from_buffer(data)?))
+    }
+
+    /// Remove the metadata selected by `policy` from an image and save to output_path.
+    /// Formats with a native parser are rewritten without re-encoding; TIFF and unknown formats go through rexiv2.
+    /// The output is written to a temporary file and validated before it replaces output_path,
+    /// so a crash or a full disk never leaves a truncated image behind. `check` runs on the
+    /// temporary file after the built-in validation (e.g. `--verify`), with the same effect.
+    pub fn remove_metadata(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        policy: &RemovalPolicy,
+        check: impl FnOnce(&Path) -> Result<()>,
+    ) -> Result<()> {
         if !input_path.exists() {
             anyhow::bail!("Input file does not exist: {}", input_path.display());
         }
+        let data = std::fs::read(input_path)?;
+        let format = ImageFormat::detect(&data);
+        let validate = |path: &Path| {
+            self.validate_cleaned(format, path)?;
+            check(path)
+        };
+        match format {
+            Some(ImageFormat::Tiff) if formats::tiff::is_raw(input_path, &data) => anyhow::bail!(
+                "{} is a camera RAW file; removing metadata from RAW files is not supported",
+                input_path.display()
+            ),
+            Some(ImageFormat::Tiff) | None => {
+                if policy.icc_mode() == IccMode::Srgb && formats::icc_profile(&data).is_some() {
+                    anyhow::bail!(
+                        "Replacing the ICC profile of {} is not supported; use --icc keep or --icc strip",
+                        input_path.display()
+                    );
+                }
+                // rexiv2 rewrites the metadata of an existing file, so start from a copy of the input
+                let write = |temp: &Path| {
+                    std::fs::copy(input_path, temp).context("Failed to copy the image")?;
+                    self.remove_metadata_rexiv2(input_path, temp, policy)
+                };
+                atomic::write_file(output_path, input_path, write, validate)
+            }
+            Some(format) => {
+                let cleaned = formats::clean(format, &data, policy)
+                    .with_context(|| format!("Failed to rewrite {} without metadata", format))?;
+                let write = |temp: &Path| std::fs::write(temp, &cleaned).context("Failed to save image without metadata");
+                atomic::write_file(output_path, input_path, write, validate)
+            }
+        }
+    }
+
+    /// Remove the metadata selected by `policy` from an image held in memory, and return the
+    /// cleaned image. The format is detected from the magic bytes. Only the natively rewritten
+    /// formats are supported: rexiv2 can only save to a file.
+    pub fn clean_buffer(&self, data: &[u8], policy: &RemovalPolicy) -> Result<Vec<u8>> {
+        let format = match ImageFormat::detect(data) {
+            Some(ImageFormat::Tiff) => anyhow::bail!("TIFF and RAW images can only be cleaned from a file"),
+            Some(format) => format,
+            None => anyhow::bail!("Unrecognized image format; only JPEG, PNG, WebP, GIF, HEIF and AVIF can be cleaned in memory"),
+        };
+        let cleaned = formats::clean(format, data, policy)
+            .with_context(|| format!("Failed to rewrite {} without metadata", format))?;
+        self.validate_buffer(Some(format), &cleaned)
+            .context("The cleaned image is invalid")?;
+        Ok(cleaned)
+    }
+
+    /// Check that a cleaned image is still readable: same format as the original, and its
+    /// structure parses again
+    fn validate_cleaned(&self, format: Option<ImageFormat>, path: &Path) -> Result<()> {
+        let data = std::fs::read(path)?;
+        self.validate_buffer(format, &data)?;
+        if format.is_none() {
+            rexiv2::Metadata::new_from_path(path).context("Failed to read the cleaned image back with rexiv2")?;
+        }
+        Ok(())
+    }
+
+    /// The checks of `validate_cleaned` that need only the bytes
+    fn validate_buffer(&self, format: Option<ImageFormat>, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            anyhow::bail!("the cleaned image is empty");
+        }
+        if ImageFormat::detect(data) != format {
+            anyhow::bail!("the cleaned image is no longer a {} file", format.map_or("valid".to_string(), |f| f.to_string()));
+        }
+        if format.is_some() {
+            formats::scan_containers(data)?;
+        }
+        Ok(())
+    }
+
+    /// Remove metadata through rexiv2. Comments and the EXIF thumbnail are always removed.
+    /// The ICC profile (a TIFF tag) follows the policy's ICC mode.
+    fn remove_metadata_rexiv2(&self, input_path: &Path, output_path: &Path, policy: &RemovalPolicy) -> Result<()> {
         let image = rexiv2::Metadata::new_from_path(input_path)
             .context("Failed to open image with rexiv2")?;
-        image.clear();
+        if policy.removes_everything() && policy.icc_mode() == IccMode::Strip {
+            image.clear();
+        } else {
+            let tags = [image.get_exif_tags(), image.get_xmp_tags(), image.get_iptc_tags()];
+            for tag in tags.into_iter().flat_map(|t| t.unwrap_or_default()) {
+                let remove = if tag == ICC_TAG {
+                    policy.icc_mode() == IccMode::Strip
+                } else {
+                    policy.should_remove(&tag)
+                };
+                if remove {
+                    image.clear_tag(&tag);
+                }
+            }
+            image.clear_comment();
+            image.erase_thumbnail();
+        }
         image.save_to_file(output_path)
             .context("Failed to save image without metadata using rexiv2")?;
         Ok(())
     }
 
-    /// Extract all available metadata from an image
-    fn extract_metadata(&self, path: &Path) -> Result<HashMap<String, String>> {
-        let mut metadata = HashMap::new();
+    /// Extract all available metadata from an image file
+    fn extract_metadata(&self, path: &Path) -> Result<Vec<MetadataEntry>> {
+        let data = std::fs::read(path)?;
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        self.extract_entries(&data, modified)
+    }
+
+    /// Extract all available metadata from the bytes of an image, in a stable order: EXIF,
+    /// XMP, IPTC, native container metadata, then the file properties
+    fn extract_entries(&self, data: &[u8], modified: Option<SystemTime>) -> Result<Vec<MetadataEntry>> {
+        let mut entries = Vec::new();
         // EXIF
-        if let Ok(exif_data) = self.extract_exif_metadata(path) {
-            for (k, v) in exif_data {
-                metadata.entry(k).or_insert(v);
-            }
+        if let Ok(exif_entries) = self.extract_exif_metadata(data) {
+            entries.extend(exif_entries);
+        }
+        // XMP
+        if let Ok(xmp_entries) = self.extract_xmp_metadata(data) {
+            entries.extend(xmp_entries);
+        }
+        // IPTC
+        if let Ok(iptc_entries) = self.extract_iptc_metadata(data) {
+            entries.extend(iptc_entries);
+        }
+        // Native container metadata (PNG chunks, ICC profile, trailing data, thumbnails, ...)
+        if let Ok(native_entries) = self.extract_native_metadata(data) {
+            entries.extend(native_entries);
         }
         // File info
-        if let Ok(file_metadata) = std::fs::metadata(path) {
-            metadata.entry("File Size".to_string()).or_insert(format!("{} bytes", file_metadata.len()));
-            if let Ok(modified) = file_metadata.modified() {
-                metadata.entry("Modified".to_string()).or_insert(format!("{:?}", modified));
-            }
+        let size = data.len();
+        entries.push(MetadataEntry::file("File Size", Value::Integer(size as i64), format!("{} bytes", size)));
+        if let Some(modified) = modified {
+            let modified = chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339();
+            entries.push(MetadataEntry::file("Modified", Value::Text(modified.clone()), modified));
         }
         // Dimensions
-        if let Ok(meta) = rexiv2::Metadata::new_from_path(path) {
+        if let Ok(meta) = rexiv2::Metadata::new_from_buffer(data) {
             let width = meta.get_pixel_width();
             let height = meta.get_pixel_height();
             if width > 0 && height > 0 {
-                metadata.entry("Dimensions".to_string()).or_insert(format!("{}x{}", width, height));
+                let value = Value::List(vec![Value::Integer(width as i64), Value::Integer(height as i64)]);
+                entries.push(MetadataEntry::file("Dimensions", value, format!("{}x{}", width, height)));
             }
         }
-        Ok(metadata)
+        Ok(entries)
     }
 
-    /// Extract EXIF metadata only
-    fn extract_exif_metadata(&self, path: &Path) -> Result<HashMap<String, String>> {
-        let file = File::open(path)?;
-        let mut bufreader = BufReader::new(&file);
-        let mut metadata = HashMap::new();
-        if let Ok(exif_data) = exif::Reader::new().read_from_container(&mut bufreader) {
+    /// Extract EXIF metadata only, keyed like exiv2 (`Exif.GPSInfo.GPSLatitude`), so tags of
+    /// the primary image and of the thumbnail IFD stay apart
+    fn extract_exif_metadata(&self, data: &[u8]) -> Result<Vec<MetadataEntry>> {
+        let mut entries = Vec::new();
+        let exif_data = exif::Reader::new().read_from_container(&mut Cursor::new(data)).or_else(|e| {
+            let tiff = formats::exif_block(data).ok_or(e)?;
+            exif::Reader::new().read_raw(tiff)
+        });
+        if let Ok(exif_data) = exif_data {
             for f in exif_data.fields() {
-                let tag_name = format!("{}", f.tag);
-                let value = f.display_value().with_unit(&exif_data).to_string();
-                metadata.insert(tag_name, value);
+                let group = exif_group(f);
+                let name = f.tag.to_string();
+                let display = f.display_value().with_unit(&exif_data).to_string();
+                let value = Value::from_exif(&f.value, &display);
+                let entry = MetadataEntry::new(Some(Container::Exif), &group, &name, format!("Exif.{}.{}", group, name), value)
+                    .with_tag_id(f.tag.number() as u32)
+                    .with_display(display);
+                entries.push(match &f.value {
+                    exif::Value::Undefined(bytes, _) => entry.with_raw(bytes.clone()),
+                    _ => entry,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Extract metadata read by the native container parsers (e.g. `Png.tEXt.Software`),
+    /// the ICC profile header and text tags (e.g. `Icc.ProfileDescription`), the trailing
+    /// data and the embedded thumbnails (e.g. `EXIF Thumbnail: 160x120, 5230 bytes`)
+    fn extract_native_metadata(&self, data: &[u8]) -> Result<Vec<MetadataEntry>> {
+        let mut entries = formats::native_entries(data)?;
+        if let Some(profile) = formats::icc_profile(data) {
+            match icc::Profile::parse(&profile) {
+                Ok(profile) => entries.extend(profile.entries()),
+                Err(_) => {
+                    let key = format!("{}Profile", ICC_PREFIX);
+                    entries.push(
+                        MetadataEntry::new(Some(Container::Icc), "Header", "Profile", key, Value::Integer(profile.len() as i64))
+                            .with_display(format!("{} bytes", profile.len())),
+                    );
+                }
+            }
+        }
+        if let Some(trailer) = formats::trailer(data) {
+            let value = Value::Integer(trailer.len as i64);
+            entries.push(
+                MetadataEntry::new(Some(Container::Trailer), "Trailer", TRAILER_KEY, TRAILER_KEY.to_string(), value)
+                    .with_display(trailer.describe()),
+            );
+        }
+        for thumbnail in formats::thumbnails(data) {
+            let key = thumbnail.kind.to_string();
+            let value = Value::Integer(thumbnail.data.len() as i64);
+            entries.push(
+                MetadataEntry::new(Some(Container::Thumbnail), "Thumbnail", &key, key.clone(), value)
+                    .with_display(thumbnail.describe()),
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Extract XMP properties, keyed by their namespaced name (e.g. `Xmp.dc.creator`).
+    /// Arrays (bags, sequences) become lists.
+    fn extract_xmp_metadata(&self, data: &[u8]) -> Result<Vec<MetadataEntry>> {
+        let meta = match rexiv2::Metadata::new_from_buffer(data) {
+            Ok(meta) if meta.has_xmp() => meta,
+            // Containers gexiv2 may not read (GIF, HEIF, AVIF): parse the bare XMP packet instead
+            opened => match formats::xmp_packet(data) {
+                Some(packet) => rexiv2::Metadata::new_from_buffer(&packet)
+                    .context("Failed to parse XMP packet")?,
+                None => opened.context("Failed to open image with rexiv2")?,
+            },
+        };
+        let mut entries = Vec::new();
+        if meta.has_xmp() {
+            for tag in meta.get_xmp_tags().unwrap_or_default() {
+                if let Ok(display) = meta.get_tag_interpreted_string(&tag) {
+                    let value = match meta.get_tag_multiple_strings(&tag) {
+                        Ok(values) if values.len() > 1 => Value::List(values.into_iter().map(Value::Text).collect()),
+                        _ => Value::Text(display.clone()),
+                    };
+                    entries.push(namespaced_entry(Container::Xmp, tag, value).with_display(display));
+                }
             }
         }
-        Ok(metadata)
+        Ok(entries)
     }
 
-    /// Check for other metadata using rexiv2 (returns false if no EXIF)
-    fn check_other_metadata(&self, path: &Path) -> Result<bool> {
-        match rexiv2::Metadata::new_from_path(path) {
-            Ok(_) => Ok(false),
-            Err(_) => Ok(false),
+    /// Extract IPTC-IIM records, keyed by their dataset name (e.g. `Iptc.Application2.Byline`).
+    /// Repeatable datasets such as Keywords become lists.
+    fn extract_iptc_metadata(&self, data: &[u8]) -> Result<Vec<MetadataEntry>> {
+        let meta = rexiv2::Metadata::new_from_buffer(data)
+            .context("Failed to open image with rexiv2")?;
+        let mut entries = Vec::new();
+        if meta.has_iptc() {
+            for tag in meta.get_iptc_tags().unwrap_or_default() {
+                if let Ok(values) = meta.get_tag_multiple_strings(&tag) {
+                    if !values.is_empty() {
+                        let display = values.join(", ");
+                        let value = Value::from_list(values.into_iter().map(Value::Text).collect());
+                        entries.push(namespaced_entry(Container::Iptc, tag, value).with_display(display));
+                    }
+                }
+            }
         }
+        Ok(entries)
+    }
+
+    /// Detect non-EXIF metadata: native segment/chunk scan for JPEG and PNG,
+    /// plus whatever rexiv2 reports for the formats it understands
+    fn check_other_metadata(&self, data: &[u8]) -> Vec<Container> {
+        let mut containers = formats::scan_containers(data).unwrap_or_default();
+        if let Ok(meta) = rexiv2::Metadata::new_from_buffer(data) {
+            if meta.has_exif() {
+                containers.push(Container::Exif);
+            }
+            if meta.has_xmp() {
+                containers.push(Container::Xmp);
+            }
+            if meta.has_iptc() {
+                containers.push(Container::Iptc);
+            }
+            if meta.get_comment().is_some_and(|c| !c.is_empty()) {
+                containers.push(Container::Comment);
+            }
+            if meta.get_thumbnail().is_some() {
+                containers.push(Container::Thumbnail);
+            }
+        }
+        containers
     }
 
     /// Display metadata as a table in stdout
-    fn display_table(&self, metadata: &HashMap<String, String>, quiet: bool) -> Result<()> {
-        let red_keys = RED_KEYS;
-        let yellow_keys = YELLOW_KEYS;
-        let green_keys = GREEN_KEYS;
-        let has_exif = metadata.keys().any(|k| k != "File Size" && k != "Modified" && k != "Dimensions");
+    fn display_table(&self, metadata: &[MetadataEntry], quiet: bool) -> Result<()> {
+        let has_exif = metadata.iter().any(|e| !e.is_file_property());
         if !has_exif {
             if !quiet {
                 eprintln!("No metadata in this image.");
-                if let Some(size) = metadata.get("File Size") {
-                    println!("File Size: {}", size);
-                }
-                if let Some(modified) = metadata.get("Modified") {
-                    println!("Modified: {}", modified);
-                }
-                if let Some(dim) = metadata.get("Dimensions") {
-                    println!("Dimensions: {}", dim);
+                for entry in metadata {
+                    println!("{}: {}", entry.key, entry.display);
                 }
             }
             return Ok(());
@@ -131,15 +440,12 @@ impl MetadataHandler {
             let mut count_yellow = 0;
             let mut count_green = 0;
             let mut count_unrec = 0;
-            for key in metadata.keys() {
-                if red_keys.contains(&key.as_str()) {
-                    count_red += 1;
-                } else if yellow_keys.contains(&key.as_str()) {
-                    count_yellow += 1;
-                } else if green_keys.contains(&key.as_str()) {
-                    count_green += 1;
-                } else {
-                    count_unrec += 1;
+            for entry in metadata {
+                match entry.sensitivity {
+                    Sensitivity::Red => count_red += 1,
+                    Sensitivity::Yellow => count_yellow += 1,
+                    Sensitivity::Green => count_green += 1,
+                    Sensitivity::Unrecognized => count_unrec += 1,
                 }
             }
             let total = count_red + count_yellow + count_green + count_unrec;
@@ -155,15 +461,13 @@ impl MetadataHandler {
             // Now print the table
             println!("{}", "─".repeat(60));
             println!("📋 Image Metadata:");
-            for (key, value) in metadata {
-                let color = if red_keys.contains(&key.as_str()) {
-                    "\x1b[31m"
-                } else if yellow_keys.contains(&key.as_str()) {
-                    "\x1b[33m"
-                } else if green_keys.contains(&key.as_str()) {
-                    "\x1b[32m"
-                } else {
-                    "\x1b[0m"
+            for entry in metadata {
+                let (key, value) = (&entry.key, &entry.display);
+                let color = match entry.sensitivity {
+                    Sensitivity::Red => "\x1b[31m",
+                    Sensitivity::Yellow => "\x1b[33m",
+                    Sensitivity::Green => "\x1b[32m",
+                    Sensitivity::Unrecognized => "\x1b[0m",
                 };
 
                 // Try to pretty-print JSON objects as sub-tags, even if value is a quoted JSON string
@@ -199,16 +503,26 @@ impl MetadataHandler {
         }
         Ok(())
     }
+}
 
-    /// Display metadata as pretty JSON in stdout
-    fn display_json(&self, metadata: &HashMap<String, String>, quiet: bool) -> Result<()> {
-        if !quiet {
-            if metadata.is_empty() {
-                eprintln!("⚠️  No Metadata found in image");
-            } else {
-                println!("{}", serde_json::to_string_pretty(metadata)?);
-            }
-        }
-        Ok(())
+/// exiv2 group of an EXIF field: the IFD it was read from (`Image`, `Photo`, `GPSInfo`, `Thumbnail`, ...)
+fn exif_group(field: &exif::Field) -> String {
+    match field.tag.context() {
+        exif::Context::Exif => "Photo".to_string(),
+        exif::Context::Gps => "GPSInfo".to_string(),
+        exif::Context::Interop => "Iop".to_string(),
+        _ => match field.ifd_num {
+            exif::In::PRIMARY => "Image".to_string(),
+            exif::In::THUMBNAIL => "Thumbnail".to_string(),
+            exif::In(n) => format!("Image{}", n + 1),
+        },
     }
-}
\ No newline at end of file
+}
+
+/// Entry for an exiv2 key (`Xmp.dc.creator`, `Iptc.Application2.Keywords`), split into its group and name
+fn namespaced_entry(container: Container, key: String, value: Value) -> MetadataEntry {
+    let mut parts = key.splitn(3, '.').skip(1);
+    let group = parts.next().unwrap_or_default().to_string();
+    let name = parts.next().unwrap_or(&key).to_string();
+    MetadataEntry::new(Some(container), &group, &name, key, value)
+}
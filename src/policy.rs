@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use crate::formats::icc::ICC_PREFIX;
+use crate::formats::trailer::TRAILER_KEY;
+use crate::sensitivity::{classify_key, tag_name, Sensitivity};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// Privacy presets for `clean`, based on the RED/YELLOW/GREEN classification
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum PrivacyLevel {
+    /// Strip only insecure (🔴) fields
+    Red,
+    /// Strip insecure (🔴) and better-to-remove (🟡) fields
+    Yellow,
+    /// Strip everything, including safe fields and the ICC profile
+    All,
+}
+
+/// What `clean` does with the ICC color profile
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum IccMode {
+    /// Keep the profile as it is
+    Keep,
+    /// Remove the profile (colors may render differently)
+    Strip,
+    /// Replace an RGB profile with a standard sRGB profile
+    Srgb,
+}
+
+/// Command-line options selecting what `clean` removes, shared by `verify`
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct PolicyArgs {
+    /// Privacy preset: red strips insecure fields, yellow strips insecure and better-to-remove fields, all strips everything
+    #[arg(long, value_enum)]
+    pub level: Option<PrivacyLevel>,
+    /// Tags to keep, as comma-separated glob patterns (default: the safe GREEN tags, e.g. Orientation, ColorSpace)
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    pub keep: Vec<String>,
+    /// Only remove these tags, as comma-separated glob patterns (e.g. GPS*,Make,Model)
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    pub strip: Vec<String>,
+    /// Keep data appended after the end of the image (e.g. motion photo videos), which is removed by default
+    #[arg(long)]
+    pub keep_trailer: bool,
+    /// What to do with the ICC color profile: keep it, strip it, or replace an RGB profile with
+    /// a standard sRGB one (default: keep, strip with --level all)
+    #[arg(long, value_enum)]
+    pub icc: Option<IccMode>,
+}
+
+impl PolicyArgs {
+    pub fn policy(&self) -> Result<RemovalPolicy> {
+        Ok(RemovalPolicy::new(self.level, &self.keep, &self.strip)?
+            .with_trailer(self.keep_trailer)
+            .with_icc(self.icc))
+    }
+}
+
+/// Decides which metadata tags `clean` removes.
+///
+/// Patterns are globs matched (case-insensitively) against either the full key
+/// (`Exif.GPSInfo.GPSLatitude`, `Xmp.dc.creator`) or the bare tag name (`GPSLatitude`).
+pub struct RemovalPolicy {
+    /// Preset selecting fields by sensitivity. `None` removes everything but the GREEN
+    /// (safe to share) tags, unless `keep` or `strip` are given.
+    level: Option<PrivacyLevel>,
+    /// Tags that are never removed
+    keep: Option<Vec<Pattern>>,
+    /// If not empty, only these tags are removed
+    strip: Vec<Pattern>,
+    /// Keep the data that follows the end of the image (removed by default)
+    keep_trailer: bool,
+    /// ICC profile handling. `None` keeps the profile unless the policy removes everything.
+    icc: Option<IccMode>,
+}
+
+impl RemovalPolicy {
+    pub fn new(level: Option<PrivacyLevel>, keep: &[String], strip: &[String]) -> Result<Self> {
+        let keep = if keep.is_empty() { None } else { Some(compile(keep)?) };
+        Ok(RemovalPolicy { level, keep, strip: compile(strip)?, keep_trailer: false, icc: None })
+    }
+
+    /// Keep (or not) the data appended after the end of the image
+    pub fn with_trailer(mut self, keep: bool) -> Self {
+        self.keep_trailer = keep;
+        self
+    }
+
+    /// Whether the data appended after the end of the image is kept
+    pub fn keeps_trailer(&self) -> bool {
+        self.keep_trailer
+    }
+
+    /// Choose what happens to the ICC profile, instead of the level's default
+    pub fn with_icc(mut self, icc: Option<IccMode>) -> Self {
+        self.icc = icc;
+        self
+    }
+
+    /// What happens to the ICC profile: kept by default, stripped by `--level all` alone
+    pub fn icc_mode(&self) -> IccMode {
+        match self.icc {
+            Some(mode) => mode,
+            None if self.removes_everything() => IccMode::Strip,
+            None => IccMode::Keep,
+        }
+    }
+
+    /// Whether the tag with the given key should be removed
+    pub fn should_remove(&self, key: &str) -> bool {
+        if key == TRAILER_KEY {
+            return !self.keep_trailer;
+        }
+        // The profile goes as a whole, following the ICC mode rather than tag patterns
+        if key.starts_with(ICC_PREFIX) {
+            return self.icc_mode() != IccMode::Keep;
+        }
+        if self.keep.as_ref().is_some_and(|keep| matches_any(keep, key)) {
+            return false;
+        }
+        if !self.strip.is_empty() && !matches_any(&self.strip, key) {
+            return false;
+        }
+        let sensitivity = classify_key(key);
+        match self.level {
+            Some(PrivacyLevel::Red) => sensitivity == Sensitivity::Red,
+            Some(PrivacyLevel::Yellow) => matches!(sensitivity, Sensitivity::Red | Sensitivity::Yellow),
+            Some(PrivacyLevel::All) => true,
+            // The GREEN default only applies when the user did not pick the tags to keep or strip
+            None => self.keep.is_some() || !self.strip.is_empty() || sensitivity != Sensitivity::Green,
+        }
+    }
+
+    /// Whether every piece of metadata goes, including the ICC profile (`--level all` alone)
+    pub fn removes_everything(&self) -> bool {
+        self.level == Some(PrivacyLevel::All) && self.keep.is_none() && self.strip.is_empty()
+    }
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(|p| Pattern::new(p).with_context(|| format!("Invalid tag pattern '{}'", p)))
+        .collect()
+}
+
+fn matches_any(patterns: &[Pattern], key: &str) -> bool {
+    let name = tag_name(key);
+    patterns
+        .iter()
+        .any(|p| p.matches_with(key, MATCH_OPTIONS) || p.matches_with(name, MATCH_OPTIONS))
+}
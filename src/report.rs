@@ -0,0 +1,265 @@
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::entry::MetadataEntry;
+use crate::formats::ImageFormat;
+use crate::sensitivity::Sensitivity;
+
+/// Version of the [`Report`] layout, bumped on any incompatible change (renamed or retyped fields)
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Output formats of `show`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Colored table for the terminal
+    Table,
+    /// The report as one pretty-printed JSON document
+    Json,
+    /// One JSON record per field
+    Ndjson,
+    /// One row per field, with a header
+    Csv,
+    /// The report as a YAML document
+    Yaml,
+    /// A Markdown table, e.g. to paste into tickets
+    Markdown,
+}
+
+/// Columns of the CSV output
+pub const CSV_HEADER: [&str; 10] = ["file", "container", "group", "tag_id", "name", "key", "value", "display", "sensitivity", "raw"];
+
+/// Metadata of one image, as printed by `show --format json`.
+///
+/// Fields are listed in a fixed order (EXIF, XMP, IPTC, native container metadata, then file
+/// properties), so the output of two runs on the same file is identical and diffs cleanly.
+/// Downstream crates can read it back with `serde_json::from_str::<medars::report::Report>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub schema_version: u32,
+    /// Path of the image, as given on the command line
+    pub file: PathBuf,
+    /// Format detected from the magic bytes; `None` for formats without a native parser
+    pub format: Option<ImageFormat>,
+    pub fields: Vec<MetadataEntry>,
+}
+
+impl Report {
+    pub fn new(file: &Path, format: Option<ImageFormat>, fields: Vec<MetadataEntry>) -> Self {
+        Report {
+            schema_version: SCHEMA_VERSION,
+            file: file.to_path_buf(),
+            format,
+            fields,
+        }
+    }
+
+    /// Whether the image has any embedded metadata, beyond the file properties
+    pub fn has_metadata(&self) -> bool {
+        self.fields.iter().any(|e| !e.is_file_property())
+    }
+
+    /// Whether the image has any GPS field (EXIF GPS IFD or XMP `exif:GPS*` properties)
+    pub fn has_gps(&self) -> bool {
+        self.fields.iter().any(|e| e.is_gps())
+    }
+
+    /// Write one JSON record per line for each field, carrying the schema version and the file
+    pub fn write_ndjson(&self, out: &mut impl Write) -> Result<()> {
+        #[derive(Serialize)]
+        struct Record<'a> {
+            schema_version: u32,
+            file: &'a Path,
+            #[serde(flatten)]
+            field: &'a MetadataEntry,
+        }
+        for field in &self.fields {
+            let record = Record { schema_version: self.schema_version, file: &self.file, field };
+            serde_json::to_writer(&mut *out, &record)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Write one CSV row per field, in the order of [`CSV_HEADER`]. Typed values are written
+    /// as text (lists joined with ", ") and raw bytes hex-encoded.
+    pub fn write_csv<W: Write>(&self, out: &mut csv::Writer<W>) -> Result<()> {
+        for field in &self.fields {
+            out.write_record([
+                self.file.display().to_string(),
+                field.container.map(|c| format!("{:?}", c)).unwrap_or_default(),
+                field.group.clone(),
+                field.tag_id.map(|id| id.to_string()).unwrap_or_default(),
+                field.name.clone(),
+                field.key.clone(),
+                field.value.to_string(),
+                field.display.clone(),
+                format!("{:?}", field.sensitivity),
+                field.raw.as_ref().map(|raw| raw.iter().map(|b| format!("{:02x}", b)).collect()).unwrap_or_default(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Write the report as a Markdown section: a heading with the file and its format, then
+    /// a table of the fields
+    pub fn write_markdown(&self, out: &mut impl Write) -> Result<()> {
+        match self.format {
+            Some(format) => writeln!(out, "## {} ({})", self.file.display(), format)?,
+            None => writeln!(out, "## {}", self.file.display())?,
+        }
+        writeln!(out)?;
+        writeln!(out, "| Sensitivity | Key | Value |")?;
+        writeln!(out, "|---|---|---|")?;
+        for field in &self.fields {
+            let sensitivity = match field.sensitivity {
+                Sensitivity::Red => "🔴 Insecure",
+                Sensitivity::Yellow => "🟡 Better to remove",
+                Sensitivity::Green => "🟢 Safe to share",
+                Sensitivity::Unrecognized => "⚪ Unrecognized",
+            };
+            writeln!(out, "| {} | `{}` | {} |", sensitivity, field.key, markdown_cell(&field.display))?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the reports as one pretty-printed JSON document: the report itself for a single
+/// file, an array for a `batch` of files
+pub fn write_json(out: &mut impl Write, reports: &[Report], batch: bool) -> Result<()> {
+    match reports {
+        [report] if !batch => serde_json::to_writer_pretty(&mut *out, report)?,
+        _ => serde_json::to_writer_pretty(&mut *out, reports)?,
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write the reports as one YAML document: the report itself for a single file, a sequence
+/// for a `batch` of files
+pub fn write_yaml(out: &mut impl Write, reports: &[Report], batch: bool) -> Result<()> {
+    match reports {
+        [report] if !batch => serde_yaml::to_writer(out, report)?,
+        _ => serde_yaml::to_writer(out, reports)?,
+    }
+    Ok(())
+}
+
+/// Counts printed after a batch of files, e.g. "37 of 120 files contain GPS"
+pub struct Summary {
+    pub files: usize,
+    pub with_metadata: usize,
+    pub with_gps: usize,
+}
+
+impl Summary {
+    pub fn new(reports: &[Report]) -> Self {
+        Summary {
+            files: reports.len(),
+            with_metadata: reports.iter().filter(|r| r.has_metadata()).count(),
+            with_gps: reports.iter().filter(|r| r.has_gps()).count(),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📊 {} of {} files contain metadata", self.with_metadata, self.files)?;
+        write!(f, "📍 {} of {} files contain GPS", self.with_gps, self.files)
+    }
+}
+
+/// Escape a value for a Markdown table cell
+fn markdown_cell(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::Value;
+    use crate::formats::Container;
+
+    #[test]
+    fn report_round_trips_through_json() {
+        let fields = vec![
+            MetadataEntry::new(
+                Some(Container::Exif),
+                "GPSInfo",
+                "GPSLatitude",
+                "Exif.GPSInfo.GPSLatitude".to_string(),
+                Value::List(vec![
+                    Value::Rational { num: 40, den: 1 },
+                    Value::Rational { num: 26, den: 1 },
+                    Value::Rational { num: 4610, den: 100 },
+                ]),
+            )
+            .with_tag_id(2),
+            MetadataEntry::new(Some(Container::Exif), "Photo", "ExifVersion", "Exif.Photo.ExifVersion".to_string(), Value::Text("2.32".to_string()))
+                .with_raw(b"0232".to_vec()),
+            MetadataEntry::file("File Size", Value::Integer(627), "627 bytes".to_string()),
+        ];
+        let report = Report::new(Path::new("photo.jpg"), Some(ImageFormat::Jpeg), fields);
+        let json = serde_json::to_string_pretty(&report).unwrap();
+        let parsed: Report = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.schema_version, SCHEMA_VERSION);
+        assert_eq!(parsed.format, Some(ImageFormat::Jpeg));
+        assert_eq!(parsed.fields.len(), 3);
+        assert_eq!(parsed.fields[0].value, report.fields[0].value);
+        assert_eq!(parsed.fields[1].raw.as_deref(), Some(&b"0232"[..]));
+        assert_eq!(serde_json::to_string_pretty(&parsed).unwrap(), json);
+    }
+
+    fn report(file: &str, fields: Vec<MetadataEntry>) -> Report {
+        Report::new(Path::new(file), Some(ImageFormat::Jpeg), fields)
+    }
+
+    fn samples() -> Vec<Report> {
+        let gps = MetadataEntry::new(Some(Container::Exif), "GPSInfo", "GPSLatitudeRef", "Exif.GPSInfo.GPSLatitudeRef".to_string(), Value::Text("N".to_string()));
+        let creator = MetadataEntry::new(Some(Container::Xmp), "dc", "creator", "Xmp.dc.creator".to_string(), Value::Text("Jane Doe".to_string()));
+        let size = MetadataEntry::file("File Size", Value::Integer(627), "627 bytes".to_string());
+        vec![
+            report("gps.jpg", vec![gps, size.clone()]),
+            report("xmp.jpg", vec![creator, size.clone()]),
+            report("plain.jpg", vec![size]),
+        ]
+    }
+
+    #[test]
+    fn summary_counts_files_with_metadata_and_gps() {
+        let summary = Summary::new(&samples());
+        assert_eq!((summary.files, summary.with_metadata, summary.with_gps), (3, 2, 1));
+        assert_eq!(summary.to_string(), "📊 2 of 3 files contain metadata\n📍 1 of 3 files contain GPS");
+    }
+
+    #[test]
+    fn batches_are_written_as_arrays() {
+        let reports = samples();
+        let json = |reports: &[Report], batch| {
+            let mut out = Vec::new();
+            write_json(&mut out, reports, batch).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let yaml = |reports: &[Report], batch| {
+            let mut out = Vec::new();
+            write_yaml(&mut out, reports, batch).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let parsed: Vec<Report> = serde_json::from_str(&json(&reports, true)).unwrap();
+        assert_eq!(parsed.iter().map(|r| r.file.to_str().unwrap()).collect::<Vec<_>>(), ["gps.jpg", "xmp.jpg", "plain.jpg"]);
+        let parsed: Vec<Report> = serde_yaml::from_str(&yaml(&reports, true)).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[1].fields[0].key, "Xmp.dc.creator");
+
+        // One readable file of a batch still makes an array, a single file does not
+        assert_eq!(serde_json::from_str::<Vec<Report>>(&json(&reports[..1], true)).unwrap().len(), 1);
+        assert_eq!(serde_yaml::from_str::<Vec<Report>>(&yaml(&reports[..1], true)).unwrap().len(), 1);
+        let single: Report = serde_json::from_str(&json(&reports[..1], false)).unwrap();
+        assert_eq!(single.file, Path::new("gps.jpg"));
+        let single: Report = serde_yaml::from_str(&yaml(&reports[..1], false)).unwrap();
+        assert!(single.has_gps());
+        assert!(json(&[], true).starts_with("[]"));
+    }
+}
@@ -0,0 +1,102 @@
+// Sensitivity classification, shared by the reports, the privacy levels of `clean` and the TUI
+
+pub const RED_KEYS: [&str; 59] = [
+    "GPSLatitude", "GPSLongitude", "GPSAltitude", "GPSLatitudeRef", "GPSLongitudeRef", "GPSAltitudeRef",
+    "DateTimeOriginal", "DateTimeDigitized", "DateTime", "OffsetTime", "OffsetTimeOriginal", "OffsetTimeDigitized", 
+    "Modified", "GPSTimeStamp", "GPSSpeedRef","GPSDateStamp", "GPSProcessingMethod", "GPSSpeed", "GPSTrack", "GPSImgDirection", 
+    "ImageUniqueID", "SubSecTime", "SubSecTimeDigitized", "SubSecTimeOriginal", "ExposureIndex", "LensModel", "MakerNote",
+    // XMP properties (dc, photoshop, xmp, xmpMM, Iptc4xmpCore, aux)
+    "creator", "City", "State", "Country", "CountryCode", "Location", "CreatorContactInfo",
+    "DateCreated", "CreateDate", "ModifyDate", "MetadataDate", "History", "SerialNumber",
+    // IPTC-IIM records (byline and location)
+    "Byline", "BylineTitle", "SubLocation", "ProvinceState", "CountryName", "LocationName", "LocationCode",
+    "LocationCreated", "TimeCreated", "DigitizationDate", "DigitizationTime",
+    // PNG text keywords and chunks
+    "Author", "Creation Time", "tIME",
+    // Embedded previews, which can show the original uncropped photo
+    "EXIF Thumbnail", "MPF Preview", "JFIF Thumbnail", "HEIF Thumbnail",
+    // Data appended after the image (motion photo videos, archives, ...)
+    "Trailing Data"
+];
+
+pub const YELLOW_KEYS: [&str; 99] = [
+    "Make", "Model", "Software", "SceneCaptureType", "DigitalZoomRatio", "FNumber", "ExposureBiasValue",
+    "ExposureMode", "MeteringMode", "ShutterSpeedValue", "ExposureTime", "WhiteBalance", "ApertureValue",
+    "FocalLength", "FocalLengthIn35mmFilm", "PhotographicSensitivity", "Flash", "ExposureProgram", "ExifVersion",
+    "MaxApertureValue", "SceneType", "BrightnessValue", "SensingMethod", "ComponentsConfiguration", 
+    "LightSource", "FlashpixVersion", "InteroperabilityIndex", "InteroperabilityVersion", "HostComputer",
+    "Tag(Exif, 34953)", "Tag(Exif, 42593)", "Tag(Exif, 34965)", "Tag(Tiff, 39424)", "Tag(Exif, 39321)", 
+    "Tag(Tiff, 34970)", "Tag(Tiff, 34979)", "Tag(Exif, 34974)", "Tag(Exif, 39424)", "Tag(Tiff, 39321)",
+    "Artist", "Copyright", "ImageDescription", "UserComment", "DocumentName", "PageName",
+    "LensMake", "LensSerialNumber", "LensSpecification",
+    "SubjectDistance", "SubjectDistanceRange", "Contrast", "Saturation", "Sharpness",
+    "GainControl", "CustomRendered", "CompositeImage", "RelatedSoundFile",
+    "WaterDepth", "Acceleration", "CameraElevationAngle", 
+    "Keywords", "Caption", "Credit",
+    // XMP properties
+    "CreatorTool", "rights", "description", "title", "subject", "Source", "Headline", "Instructions",
+    "AuthorsPosition", "CaptionWriter", "DocumentID", "InstanceID", "OriginalDocumentID", "DerivedFrom",
+    // IPTC-IIM records
+    "Writer", "Contact", "ObjectName", "Program", "ProgramVersion", "SpecialInstructions", "TransmissionReference",
+    // PNG text keywords
+    "Title", "Description", "Comment", "Disclaimer", "Warning",
+    // ICC profile header and text tags
+    "ProfileDescription", "ProfileCopyright", "DeviceManufacturer", "DeviceModel", "DeviceMfgDesc",
+    "DeviceModelDesc", "ProfileDateTime", "ProfileCreator", "ProfileCMMType", "PrimaryPlatform"
+];
+
+pub const GREEN_KEYS: [&str; 27] = [
+    "PixelXDimension", "PixelYDimension", "ImageWidth", "ImageLength", "Dimensions", "Compression", "ColorSpace",
+    "XResolution", "YResolution", "ResolutionUnit", "YCbCrPositioning", "JPEGInterchangeFormat", 
+    "JPEGInterchangeFormatLength", "File Size", "Orientation",
+    "BitsPerSample", "PhotometricInterpretation", "PlanarConfiguration", "TransferFunction",
+    "WhitePoint", "PrimaryChromaticities", "ColorMap",
+    // XMP properties
+    "format",
+    // ICC profile header
+    "ProfileVersion", "ProfileClass", "ColorSpaceData", "ProfileConnectionSpace"
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+pub enum Sensitivity {
+    Red,
+    Yellow,
+    Green,
+    Unrecognized,
+}
+
+/// Name used to look a metadata key up in the sensitivity lists.
+/// Namespaced keys (`Exif.Image.Make`, `Xmp.exif.GPSLatitude`, `Xmp.xmpMM.History[1]/stEvt:when`,
+/// `Iptc.Application2.City`, `Png.tEXt.Software`, `Jpeg.Comment`, `Icc.ProfileDescription`) are reduced to
+/// their property name so they share the EXIF classification.
+pub fn tag_name(key: &str) -> &str {
+    let namespaced = key
+        .strip_prefix("Exif.")
+        .or_else(|| key.strip_prefix("Xmp."))
+        .or_else(|| key.strip_prefix("Iptc."))
+        .or_else(|| key.strip_prefix("Png."))
+        .or_else(|| key.strip_prefix("Jpeg."))
+        .or_else(|| key.strip_prefix("Gif."))
+        .or_else(|| key.strip_prefix("Icc."));
+    match namespaced {
+        Some(rest) => {
+            let property = rest.split_once('.').map(|(_, p)| p).unwrap_or(rest);
+            property.split(['[', '/']).next().unwrap_or(property)
+        }
+        None => key,
+    }
+}
+
+/// Classify a metadata key as RED (insecure), YELLOW (better to remove) or GREEN (safe)
+pub fn classify_key(key: &str) -> Sensitivity {
+    let name = tag_name(key);
+    if RED_KEYS.contains(&name) {
+        Sensitivity::Red
+    } else if YELLOW_KEYS.contains(&name) {
+        Sensitivity::Yellow
+    } else if GREEN_KEYS.contains(&name) {
+        Sensitivity::Green
+    } else {
+        Sensitivity::Unrecognized
+    }
+}
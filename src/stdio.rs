@@ -0,0 +1,56 @@
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+use anyhow::{Context, Result};
+
+/// The file argument that stands for standard input (or output, for `--output`)
+pub const STDIO: &str = "-";
+
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+/// Whether the file arguments ask for standard input. `-` has to be the only one, as
+/// several inputs would not fit in one output stream.
+pub fn reads_stdin(files: &[String]) -> Result<bool> {
+    match files {
+        [file] if file == STDIO => Ok(true),
+        _ if files.iter().any(|f| f == STDIO) => anyhow::bail!("'-' (standard input) cannot be combined with other files"),
+        _ => Ok(false),
+    }
+}
+
+/// Read a whole image from standard input
+pub fn read_stdin() -> Result<Vec<u8>> {
+    let mut stdin = std::io::stdin().lock();
+    if stdin.is_terminal() {
+        anyhow::bail!("No image on standard input; pipe one in, e.g. `medars show - < photo.jpg`");
+    }
+    let mut data = Vec::new();
+    stdin.read_to_end(&mut data).context("Failed to read standard input")?;
+    if data.is_empty() {
+        anyhow::bail!("Standard input is empty");
+    }
+    Ok(data)
+}
+
+/// Write an image to standard output, refusing to dump binary data on a terminal
+pub fn write_stdout(data: &[u8]) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    if stdout.is_terminal() {
+        anyhow::bail!("Refusing to write image data to a terminal; redirect standard output, e.g. `> clean.jpg`");
+    }
+    stdout.write_all(data).and_then(|_| stdout.flush()).context("Failed to write standard output")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dash_must_be_the_only_file() {
+        let files = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert!(reads_stdin(&files(&["-"])).unwrap());
+        assert!(!reads_stdin(&files(&["a.jpg", "b.jpg"])).unwrap());
+        assert!(reads_stdin(&files(&["a.jpg", "-"])).is_err());
+    }
+}
@@ -121,6 +121,11 @@ pub struct App {
     // Image picker for loading images
     pub image_picker: Option<Picker>,
 
+    // Embedded thumbnail shown under the image ('t' toggles it)
+    pub show_thumbnail: bool,
+    pub thumbnail_state: Option<StatefulProtocol>,
+    pub thumbnail_label: Option<String>,
+
     pub terminal_width: Option<u16>,
     pub terminal_height: Option<u16>,
 }
@@ -153,6 +158,9 @@ impl App {
             pending_current_load: None, // No pending loads initially
             last_loaded_path: None, // No previously loaded image
             image_picker: picker,
+            show_thumbnail: false,
+            thumbnail_state: None,
+            thumbnail_label: None,
             terminal_width: None,
             terminal_height: None,
         }
@@ -254,6 +262,33 @@ impl App {
             }
             self.previous_selected = self.selected;
             self.mid_scroll = 0;
+            self.update_thumbnail();
+        }
+    }
+
+    /// Load the embedded thumbnail of the selected file, when the thumbnail view is on.
+    /// Thumbnails are small, so they are decoded right away instead of in the background.
+    fn update_thumbnail(&mut self) {
+        self.thumbnail_state = None;
+        self.thumbnail_label = None;
+        if !self.show_thumbnail {
+            return;
+        }
+        let Some(path) = self.image_path.as_ref() else {
+            return;
+        };
+        let thumbnails = std::fs::read(path)
+            .map(|data| crate::formats::thumbnails(&data))
+            .unwrap_or_default();
+        // Prefer a thumbnail that can be previewed (HEIF items cannot)
+        let decoded = thumbnails.iter().find_map(|t| t.to_image().map(|image| (t, image)));
+        let shown = decoded.as_ref().map(|(t, _)| *t).or(thumbnails.first());
+        self.thumbnail_label = Some(match shown {
+            Some(thumbnail) => format!("{}: {}", thumbnail.kind, thumbnail.describe()),
+            None => "No embedded thumbnail".to_string(),
+        });
+        if let (Some((_, image)), Some(picker)) = (decoded, self.image_picker.as_ref()) {
+            self.thumbnail_state = Some(picker.new_resize_protocol(image));
         }
     }
 
@@ -339,6 +374,10 @@ impl App {
     pub fn handle_input(&mut self, key: crossterm::event::KeyCode, max_scroll: u16, _dir: &std::path::Path) {
         match key {
             crossterm::event::KeyCode::Char('q') => self.running = false,
+            crossterm::event::KeyCode::Char('t') => {
+                self.show_thumbnail = !self.show_thumbnail;
+                self.update_thumbnail();
+            }
             // Panel focus switching
             crossterm::event::KeyCode::Right | crossterm::event::KeyCode::Char('l') => {
                 self.focused_panel = match self.focused_panel {
@@ -379,14 +418,7 @@ impl App {
     }
 
     fn is_image_file(&self, path: &std::path::Path) -> bool {
-        if let Some(ext) = path.extension() {
-            match ext.to_string_lossy().to_lowercase().as_str() {
-                "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "tif" | "webp" => true,
-                _ => false,
-            }
-        } else {
-            false
-        }
+        crate::formats::is_image_file(path)
     }
 
     /// Start loading an image in the background
@@ -18,8 +18,24 @@ pub fn render_image_panel(
     image_state: Option<&mut StatefulProtocol>,
     load_status: ImageLoadStatus,
     _file_path: Option<&str>,
+    thumbnail: Option<(&str, Option<&mut StatefulProtocol>)>,
 ) {
     use ratatui::prelude::Alignment;
+
+    // Embedded thumbnail in the lower part of the panel
+    let area = match thumbnail {
+        Some((label, thumbnail_state)) => {
+            let split = area.height * 3 / 5;
+            render_thumbnail(
+                f,
+                Rect { y: area.y + split, height: area.height - split, ..area },
+                label,
+                thumbnail_state,
+            );
+            Rect { height: split, ..area }
+        }
+        None => area,
+    };
     
     if let Some(state) = image_state {
         let available_area = Rect {
@@ -73,3 +89,28 @@ pub fn render_image_panel(
     };
     f.render_widget(file_name_widget, inner_area);
 }
+
+/// Render the embedded thumbnail with a label describing it
+fn render_thumbnail(f: &mut Frame, area: Rect, label: &str, state: Option<&mut StatefulProtocol>) {
+    let label_area = Rect {
+        x: area.x + 1,
+        y: area.y,
+        width: area.width.saturating_sub(2),
+        height: 1,
+    };
+    let label_widget = Paragraph::new(label)
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+    f.render_widget(label_widget, label_area);
+
+    if let Some(state) = state {
+        let image_area = Rect {
+            x: area.x + 3,
+            y: area.y + 1,
+            width: area.width.saturating_sub(6),
+            height: area.height.saturating_sub(2),
+        };
+        let widget = StatefulImage::default().resize(Resize::Fit(None));
+        f.render_stateful_widget(widget, image_area, state);
+    }
+}
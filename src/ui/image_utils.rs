@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use crate::entry::MetadataEntry;
 use crate::metadata::MetadataHandler;
+use crate::sensitivity::Sensitivity;
 
 /// Utility struct for image-related (non-TUI) logic
 pub struct ImageUtils {
@@ -7,38 +8,6 @@ pub struct ImageUtils {
     pub cached_metadata: Option<(String, String)>, // (filename, formatted_metadata)
 }
 
-// Sensitivity classification 
-pub const RED_KEYS: [&str; 27] = [
-    "GPSLatitude", "GPSLongitude", "GPSAltitude", "GPSLatitudeRef", "GPSLongitudeRef", "GPSAltitudeRef",
-    "DateTimeOriginal", "DateTimeDigitized", "DateTime", "OffsetTime", "OffsetTimeOriginal", "OffsetTimeDigitized", 
-    "Modified", "GPSTimeStamp", "GPSSpeedRef","GPSDateStamp", "GPSProcessingMethod", "GPSSpeed", "GPSTrack", "GPSImgDirection", 
-    "ImageUniqueID", "SubSecTime", "SubSecTimeDigitized", "SubSecTimeOriginal", "ExposureIndex", "LensModel", "MakerNote"
-];
-
-pub const YELLOW_KEYS: [&str; 65] = [
-    "Make", "Model", "Software", "SceneCaptureType", "DigitalZoomRatio", "FNumber", "ExposureBiasValue",
-    "ExposureMode", "MeteringMode", "ShutterSpeedValue", "ExposureTime", "WhiteBalance", "ApertureValue",
-    "FocalLength", "FocalLengthIn35mmFilm", "PhotographicSensitivity", "Flash", "ExposureProgram", "ExifVersion",
-    "MaxApertureValue", "SceneType", "BrightnessValue", "SensingMethod", "ComponentsConfiguration", 
-    "LightSource", "FlashpixVersion", "InteroperabilityIndex", "InteroperabilityVersion", "HostComputer",
-    "Tag(Exif, 34953)", "Tag(Exif, 42593)", "Tag(Exif, 34965)", "Tag(Tiff, 39424)", "Tag(Exif, 39321)", 
-    "Tag(Tiff, 34970)", "Tag(Tiff, 34979)", "Tag(Exif, 34974)", "Tag(Exif, 39424)", "Tag(Tiff, 39321)",
-    "Artist", "Copyright", "ImageDescription", "UserComment", "DocumentName", "PageName",
-    "LensMake", "LensSerialNumber", "LensSpecification",
-    "SubjectDistance", "SubjectDistanceRange", "Contrast", "Saturation", "Sharpness",
-    "GainControl", "CustomRendered", "CompositeImage", "RelatedSoundFile",
-    "WaterDepth", "Acceleration", "CameraElevationAngle", 
-    "Keywords", "Caption", "Credit", "Byline", "LocationCreated"
-];
-
-pub const GREEN_KEYS: [&str; 22] = [
-    "PixelXDimension", "PixelYDimension", "ImageWidth", "ImageLength", "Dimensions", "Compression", "ColorSpace",
-    "XResolution", "YResolution", "ResolutionUnit", "YCbCrPositioning", "JPEGInterchangeFormat", 
-    "JPEGInterchangeFormatLength", "File Size", "Orientation",
-    "BitsPerSample", "PhotometricInterpretation", "PlanarConfiguration", "TransferFunction",
-    "WhitePoint", "PrimaryChromaticities", "ColorMap"
-];
-
 impl ImageUtils {
     pub fn new() -> Self {
         ImageUtils {
@@ -54,7 +23,7 @@ impl ImageUtils {
                 return cached_text.clone();
             }
         }
-        let metadata_text = match self.metadata_handler.get_metadata_map(file_path) {
+        let metadata_text = match self.metadata_handler.get_metadata(file_path) {
             Ok(metadata) => Self::format_metadata_for_tui(&metadata),
             Err(_) => format!("Error reading metadata for: {}", selected_file),
         };
@@ -63,18 +32,12 @@ impl ImageUtils {
     }
 
     /// Format metadata for TUI display similar to CLI table format
-    pub fn format_metadata_for_tui(metadata: &HashMap<String, String>) -> String {
-        let has_exif = metadata.keys().any(|k| k != "File Size" && k != "Modified" && k != "Dimensions");
+    pub fn format_metadata_for_tui(metadata: &[MetadataEntry]) -> String {
+        let has_exif = metadata.iter().any(|e| !e.is_file_property());
         if !has_exif {
             let mut result = String::from("No metadata in this image.\n");
-            if let Some(size) = metadata.get("File Size") {
-                result.push_str(&format!("File Size: {}\n", size));
-            }
-            if let Some(modified) = metadata.get("Modified") {
-                result.push_str(&format!("Modified: {}\n", modified));
-            }
-            if let Some(dim) = metadata.get("Dimensions") {
-                result.push_str(&format!("Dimensions: {}\n", dim));
+            for entry in metadata {
+                result.push_str(&format!("{}: {}\n", entry.key, entry.display));
             }
             return result;
         }
@@ -82,15 +45,12 @@ impl ImageUtils {
         let mut count_yellow = 0;
         let mut count_green = 0;
         let mut count_unrec = 0;
-        for key in metadata.keys() {
-            if RED_KEYS.contains(&key.as_str()) {
-                count_red += 1;
-            } else if YELLOW_KEYS.contains(&key.as_str()) {
-                count_yellow += 1;
-            } else if GREEN_KEYS.contains(&key.as_str()) {
-                count_green += 1;
-            } else {
-                count_unrec += 1;
+        for entry in metadata {
+            match entry.sensitivity {
+                Sensitivity::Red => count_red += 1,
+                Sensitivity::Yellow => count_yellow += 1,
+                Sensitivity::Green => count_green += 1,
+                Sensitivity::Unrecognized => count_unrec += 1,
             }
         }
         let total = count_red + count_yellow + count_green + count_unrec;
@@ -108,16 +68,14 @@ impl ImageUtils {
         result.push('\n');
         result.push_str("📋 Image Metadata:\n");
         let mut sorted_entries: Vec<_> = metadata.iter().collect();
-        sorted_entries.sort_by_key(|(key, _)| key.as_str());
-        for (key, value) in sorted_entries {
-            let category = if RED_KEYS.contains(&key.as_str()) {
-                "🔴"
-            } else if YELLOW_KEYS.contains(&key.as_str()) {
-                "🟡"
-            } else if GREEN_KEYS.contains(&key.as_str()) {
-                "🟢"
-            } else {
-                "⚪"
+        sorted_entries.sort_by_key(|e| e.key.as_str());
+        for entry in sorted_entries {
+            let (key, value) = (&entry.key, &entry.display);
+            let category = match entry.sensitivity {
+                Sensitivity::Red => "🔴",
+                Sensitivity::Yellow => "🟡",
+                Sensitivity::Green => "🟢",
+                Sensitivity::Unrecognized => "⚪",
             };
 
             // Try to pretty-print JSON values, including double-quoted/escaped JSON strings
@@ -30,6 +30,7 @@ impl RatatuiUI {
             ("d", "delete", Color::LightRed),
             ("c", "copy", Color::Green),
             ("space", "select", Color::Cyan),
+            ("t", "thumbnail", Color::Yellow),
             ("h/j/k/l", "nav", Color::White),
         ];
 
@@ -264,6 +265,11 @@ impl RatatuiUI {
                 f.render_widget(image_panel_block, chunks[2]);
                 let load_status = self.app.get_image_load_status();
                 let current_file_path = self.app.image_path.as_deref();
+                let thumbnail = self
+                    .app
+                    .thumbnail_label
+                    .as_deref()
+                    .map(|label| (label, self.app.thumbnail_state.as_mut()));
                 render_image_panel(
                     f,
                     chunks[2],
@@ -271,6 +277,7 @@ impl RatatuiUI {
                     self.app.image_state.as_mut(),
                     load_status,
                     current_file_path,
+                    thumbnail,
                 );
 
                 // Footer: keybindings
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use anyhow::Result;
+use image::ImageReader;
+use sha2::{Digest, Sha256};
+use crate::entry::MetadataEntry;
+use crate::formats::icc::ICC_PREFIX;
+use crate::formats::ImageFormat;
+use crate::metadata::MetadataHandler;
+use crate::policy::{IccMode, RemovalPolicy};
+
+/// SHA-256 of the decoded pixels (dimensions, color type and samples) of an image
+pub type PixelHash = [u8; 32];
+
+/// Decode an image and hash its pixels, or `None` for HEIF and AVIF, which the `image` crate
+/// cannot decode. The decoder is chosen from the content, not the extension, so a cleaned
+/// temporary file decodes exactly like the original.
+pub fn pixel_hash(path: &Path) -> Result<Option<PixelHash>> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)?.take(4096).read_to_end(&mut head)?;
+    if matches!(ImageFormat::detect(&head), Some(ImageFormat::Heif | ImageFormat::Avif)) {
+        return Ok(None);
+    }
+    let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+    let mut hasher = Sha256::new();
+    hasher.update(image.width().to_le_bytes());
+    hasher.update(image.height().to_le_bytes());
+    hasher.update(format!("{:?}", image.color()).as_bytes());
+    hasher.update(image.as_bytes());
+    Ok(Some(hasher.finalize().into()))
+}
+
+/// Result of comparing the pixels of a cleaned image with the original
+pub enum PixelCheck {
+    Identical,
+    Different,
+    /// The cleaned image no longer decodes
+    Undecodable(String),
+    /// The original does not decode, so there is nothing to compare
+    OriginalUndecodable(String),
+    /// The format cannot be decoded (HEIF, AVIF), so only the metadata is checked
+    Skipped,
+}
+
+/// Outcome of checking a cleaned image
+pub struct Verification {
+    /// Fields the policy should have removed but are still there
+    pub remaining: Vec<MetadataEntry>,
+    pub pixels: PixelCheck,
+}
+
+impl Verification {
+    pub fn passed(&self) -> bool {
+        self.remaining.is_empty() && matches!(self.pixels, PixelCheck::Identical | PixelCheck::Skipped)
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut problems = Vec::new();
+        if !self.remaining.is_empty() {
+            let keys: Vec<&str> = self.remaining.iter().map(|e| e.key.as_str()).collect();
+            problems.push(format!("metadata left: {}", keys.join(", ")));
+        }
+        match &self.pixels {
+            PixelCheck::Different => problems.push("pixels differ from the original".to_string()),
+            PixelCheck::Undecodable(e) => problems.push(format!("the cleaned image does not decode: {}", e)),
+            PixelCheck::OriginalUndecodable(e) => problems.push(format!("pixels not compared: the original does not decode: {}", e)),
+            PixelCheck::Identical | PixelCheck::Skipped => {}
+        }
+        if problems.is_empty() {
+            match self.pixels {
+                PixelCheck::Skipped => write!(f, "no selected metadata left, pixels not checked (HEIF and AVIF cannot be decoded)"),
+                _ => write!(f, "no selected metadata left, pixels identical"),
+            }
+        } else {
+            write!(f, "{}", problems.join("; "))
+        }
+    }
+}
+
+/// Check a cleaned image: re-read its metadata for fields `policy` removes, and compare its
+/// pixels with `original`, the pixel hash of the image before cleaning (see [`pixel_hash`])
+pub fn verify(
+    handler: &MetadataHandler,
+    policy: &RemovalPolicy,
+    cleaned: &Path,
+    original: &Result<Option<PixelHash>>,
+) -> Result<Verification> {
+    let mut remaining = handler.get_metadata(cleaned)?;
+    remaining.retain(|e| {
+        // With --icc srgb the cleaned image carries a standard profile (or its non-RGB original)
+        let expected_icc = policy.icc_mode() == IccMode::Srgb && e.key.starts_with(ICC_PREFIX);
+        !e.is_file_property() && !expected_icc && policy.should_remove(&e.key)
+    });
+    Ok(Verification { remaining, pixels: compare_pixels(original, cleaned) })
+}
+
+fn compare_pixels(original: &Result<Option<PixelHash>>, cleaned: &Path) -> PixelCheck {
+    match (original, pixel_hash(cleaned)) {
+        (Ok(None), _) => PixelCheck::Skipped,
+        (Err(e), _) => PixelCheck::OriginalUndecodable(e.to_string()),
+        (Ok(Some(_)), Err(e)) => PixelCheck::Undecodable(e.to_string()),
+        (Ok(Some(before)), Ok(after)) if after == Some(*before) => PixelCheck::Identical,
+        (Ok(Some(_)), Ok(_)) => PixelCheck::Different,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn pixels_are_compared_unless_the_format_cannot_be_decoded() {
+        let dir = tempfile::tempdir().unwrap();
+        let (png, other, copy) = (dir.path().join("a.png"), dir.path().join("b.png"), dir.path().join("copy"));
+        RgbImage::from_pixel(4, 4, Rgb([200, 0, 0])).save(&png).unwrap();
+        RgbImage::from_pixel(4, 4, Rgb([0, 200, 0])).save(&other).unwrap();
+        // The decoder is chosen from the content, whatever the name
+        std::fs::copy(&png, &copy).unwrap();
+        let original = pixel_hash(&png);
+        assert!(matches!(compare_pixels(&original, &copy), PixelCheck::Identical));
+        assert!(matches!(compare_pixels(&original, &other), PixelCheck::Different));
+
+        std::fs::write(&copy, b"\x89PNG\r\n\x1a\n truncated").unwrap();
+        assert!(matches!(compare_pixels(&original, &copy), PixelCheck::Undecodable(_)));
+        assert!(matches!(compare_pixels(&pixel_hash(&copy), &png), PixelCheck::OriginalUndecodable(_)));
+
+        let heic = dir.path().join("image.heic");
+        std::fs::write(&heic, [&24u32.to_be_bytes()[..], b"ftypheic", &[0; 4], b"mif1heic"].concat()).unwrap();
+        assert_eq!(pixel_hash(&heic).unwrap(), None);
+        assert!(matches!(compare_pixels(&pixel_hash(&heic), &heic), PixelCheck::Skipped));
+    }
+
+    #[test]
+    fn heif_images_pass_on_their_metadata_alone() {
+        let skipped = Verification { remaining: Vec::new(), pixels: PixelCheck::Skipped };
+        assert!(skipped.passed());
+        assert_eq!(skipped.to_string(), "no selected metadata left, pixels not checked (HEIF and AVIF cannot be decoded)");
+
+        let gps = MetadataEntry::new(None, "GPSInfo", "GPSLatitude", "Exif.GPSInfo.GPSLatitude".to_string(), crate::entry::Value::Integer(48));
+        let left = Verification { remaining: vec![gps], pixels: PixelCheck::Skipped };
+        assert!(!left.passed());
+        assert_eq!(left.to_string(), "metadata left: Exif.GPSInfo.GPSLatitude");
+
+        let undecodable = Verification { remaining: Vec::new(), pixels: PixelCheck::OriginalUndecodable("corrupt".to_string()) };
+        assert!(!undecodable.passed());
+    }
+}
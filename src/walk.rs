@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use glob::Pattern;
+use ignore::WalkBuilder;
+use crate::formats;
+
+/// How file arguments are expanded into the images to process
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct WalkOptions {
+    /// Process the images inside directories given as arguments, including subdirectories.
+    /// Hidden files and paths listed in .gitignore or .ignore files are skipped.
+    #[arg(short, long)]
+    pub recursive: bool,
+    /// Only process files matching these comma-separated glob patterns (e.g. '*.jpg,*.heic')
+    #[arg(long, value_name = "GLOBS", value_delimiter = ',')]
+    pub include: Vec<String>,
+    /// Skip files and directories matching these comma-separated glob patterns (e.g. 'thumbs,*.png')
+    #[arg(long, value_name = "GLOBS", value_delimiter = ',')]
+    pub exclude: Vec<String>,
+    /// Follow symbolic links to files and directories while walking (they are skipped otherwise)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// Include and exclude patterns, matched against the file name or the whole path
+#[derive(Clone)]
+struct Filters {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filters {
+    fn new(options: &WalkOptions) -> Result<Self> {
+        let parse = |patterns: &[String], flag: &str| {
+            patterns
+                .iter()
+                .map(|p| Pattern::new(p).with_context(|| format!("Invalid {} pattern '{}'", flag, p)))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Filters {
+            include: parse(&options.include, "--include")?,
+            exclude: parse(&options.exclude, "--exclude")?,
+        })
+    }
+
+    fn excludes(&self, path: &Path) -> bool {
+        matches_any(&self.exclude, path)
+    }
+
+    fn accepts_file(&self, path: &Path) -> bool {
+        !self.excludes(path) && (self.include.is_empty() || matches_any(&self.include, path))
+    }
+}
+
+fn matches_any(patterns: &[Pattern], path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy());
+    patterns
+        .iter()
+        .any(|p| p.matches_path(path) || name.as_ref().is_some_and(|n| p.matches(n)))
+}
+
+/// Expand file arguments and glob patterns into the matching paths. With `--recursive`,
+/// directories are walked for image files, in a stable order.
+pub fn collect_files(patterns: &[String], options: &WalkOptions) -> Result<Vec<PathBuf>> {
+    let filters = Filters::new(options)?;
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let paths = match glob::glob(pattern) {
+            Ok(paths) => paths,
+            Err(e) => {
+                eprintln!("Invalid pattern '{}': {}", pattern, e);
+                continue;
+            }
+        };
+        for path in paths.flatten() {
+            if !path.is_dir() {
+                if filters.accepts_file(&path) {
+                    files.push(path);
+                }
+            } else if options.recursive {
+                files.extend(walk(&path, options, &filters));
+            } else {
+                eprintln!("Skipping directory {} (use --recursive to process the images in it)", path.display());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Image files under a directory, honoring hidden files, ignore files and the filters.
+/// Symbolic links are only followed with `--follow-symlinks`, so a link to an image is
+/// skipped like a link to a directory.
+fn walk(root: &Path, options: &WalkOptions, filters: &Filters) -> Vec<PathBuf> {
+    let prune = filters.clone();
+    let walker = WalkBuilder::new(root)
+        .follow_links(options.follow_symlinks)
+        // .gitignore files apply outside of git repositories too
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| entry.depth() == 0 || !prune.excludes(entry.path()))
+        .build();
+    let mut files = Vec::new();
+    for entry in walker {
+        match entry {
+            Ok(entry) => {
+                let is_file = entry.file_type().is_some_and(|t| t.is_file());
+                if is_file && formats::is_image_file(entry.path()) && filters.accepts_file(entry.path()) {
+                    files.push(entry.into_path());
+                }
+            }
+            Err(e) => eprintln!("Failed to walk {}: {}", root.display(), e),
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A tree with images, a text file, a hidden image, an ignored directory and links
+    ///
+    /// ```text
+    /// a.jpg  b.png  notes.txt  .hidden.jpg  .ignore (skip/)
+    /// drafts/c.jpg  skip/d.jpg  link.jpg -> a.jpg  linked -> drafts
+    /// ```
+    fn sample_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("drafts")).unwrap();
+        fs::create_dir(root.join("skip")).unwrap();
+        for file in ["a.jpg", "b.png", "notes.txt", ".hidden.jpg", "drafts/c.jpg", "skip/d.jpg"] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+        fs::write(root.join(".ignore"), "skip/\n").unwrap();
+        std::os::unix::fs::symlink(root.join("a.jpg"), root.join("link.jpg")).unwrap();
+        std::os::unix::fs::symlink(root.join("drafts"), root.join("linked")).unwrap();
+        dir
+    }
+
+    fn collect(root: &Path, options: WalkOptions) -> Vec<String> {
+        let options = WalkOptions { recursive: true, ..options };
+        let files = collect_files(&[root.display().to_string()], &options).unwrap();
+        files.iter().map(|f| f.strip_prefix(root).unwrap().display().to_string()).collect()
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn walk_skips_hidden_ignored_and_linked_files() {
+        let tree = sample_tree();
+        assert_eq!(collect(tree.path(), WalkOptions::default()), ["a.jpg", "b.png", "drafts/c.jpg"]);
+        let follow = WalkOptions { follow_symlinks: true, ..Default::default() };
+        assert_eq!(collect(tree.path(), follow), ["a.jpg", "b.png", "drafts/c.jpg", "link.jpg", "linked/c.jpg"]);
+    }
+
+    #[test]
+    fn filters_match_file_names_and_paths() {
+        let tree = sample_tree();
+        let root = tree.path();
+        // A directory name prunes the whole directory
+        let exclude = WalkOptions { exclude: patterns(&["drafts", "*.png"]), ..Default::default() };
+        assert_eq!(collect(root, exclude), ["a.jpg"]);
+        let include = WalkOptions { include: patterns(&["*.jpg"]), ..Default::default() };
+        assert_eq!(collect(root, include), ["a.jpg", "drafts/c.jpg"]);
+        // Patterns with a separator are matched against the whole path
+        let include = WalkOptions { include: patterns(&["*/drafts/*"]), ..Default::default() };
+        assert_eq!(collect(root, include), ["drafts/c.jpg"]);
+        let exclude = WalkOptions { exclude: patterns(&[&format!("{}/a.*", root.display())]), ..Default::default() };
+        assert_eq!(collect(root, exclude), ["b.png", "drafts/c.jpg"]);
+        assert!(collect_files(&[], &WalkOptions { include: patterns(&["[a"]), ..Default::default() }).is_err());
+    }
+}